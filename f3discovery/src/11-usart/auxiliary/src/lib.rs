@@ -11,6 +11,9 @@ pub use stm32f3_discovery::stm32f3xx_hal::pac::usart1;
 
 pub mod monotimer;
 
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use stm32f3_discovery::stm32f3xx_hal::{
     prelude::*,
     serial::Serial,
@@ -18,6 +21,12 @@ use stm32f3_discovery::stm32f3xx_hal::{
 };
 use monotimer::MonoTimer;
 
+/// The baud rate `init` sets up USART1 with.
+pub const BAUD_RATE: u32 = 115_200;
+
+/// USART1's clock in Hz, remembered by `init` for `set_baud_rate`.
+static PCLK2: AtomicU32 = AtomicU32::new(0);
+
 pub fn init() -> (&'static mut usart1::RegisterBlock, MonoTimer, ITM) {
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
@@ -48,10 +57,11 @@ pub fn init() -> (&'static mut usart1::RegisterBlock, MonoTimer, ITM) {
         }
     };
 
-    Serial::new(dp.USART1, (tx, rx), 115_200.Bd(), clocks, &mut rcc.apb2);
+    Serial::new(dp.USART1, (tx, rx), BAUD_RATE.Bd(), clocks, &mut rcc.apb2);
+    PCLK2.store(clocks.pclk2().0, Ordering::Relaxed);
     // If you are having trouble sending/receiving data to/from the
-    // HC-05 bluetooth module, try this configuration instead:
-    // Serial::usart1(dp.USART1, (tx, rx), 9600.bps(), clocks, &mut rcc.apb2);
+    // HC-05 bluetooth module, switch to its default after `init`:
+    // aux11::set_baud_rate(usart1, 9600).unwrap();
 
     unsafe {
         (
@@ -61,3 +71,43 @@ pub fn init() -> (&'static mut usart1::RegisterBlock, MonoTimer, ITM) {
         )
     }
 }
+
+/// Changes USART1's baud rate, e.g. to 9600 for the HC-05 bluetooth module.
+///
+/// Waits for the byte that is being sent to go out first, it would get
+/// garbled otherwise. Bytes that come in while switching are lost. A rate
+/// the USART can't do leaves it as it is, so a typo coming in over the
+/// serial port can be answered over the serial port.
+pub fn set_baud_rate(
+    usart1: &usart1::RegisterBlock,
+    baud_rate: u32,
+) -> Result<(), UnsupportedBaudRate> {
+    let brr = PCLK2
+        .load(Ordering::Relaxed)
+        .checked_div(baud_rate)
+        .unwrap_or(0);
+    // The USART can't go slower than this, or faster than a sixteenth
+    // of its clock.
+    if !(16..=0xffff).contains(&brr) {
+        return Err(UnsupportedBaudRate(baud_rate));
+    }
+
+    // Transmission complete
+    while usart1.isr.read().tc().bit_is_clear() {}
+
+    usart1.cr1.modify(|_, w| w.ue().disabled());
+    usart1.brr.write(|w| w.brr().bits(brr as u16));
+    usart1.cr1.modify(|_, w| w.ue().enabled());
+    Ok(())
+}
+
+/// The baud rate `set_baud_rate` was asked for, too slow or too fast for
+/// USART1's clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedBaudRate(pub u32);
+
+impl fmt::Display for UnsupportedBaudRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported baud rate: {}", self.0)
+    }
+}
@@ -1,5 +1,7 @@
 //! This build script copies the `memory.x` file from the crate root into
-//! a directory where the linker can always find it at build time.
+//! a directory where the linker can always find it at build time. Cargo
+//! passes the directory on to every program that depends on this crate,
+//! so they don't need a `memory.x` of their own.
 //! For many projects this is optional, as the linker always searches the
 //! project root directory (wherever `Cargo.toml` is). However, if you
 //! are using a workspace or have a more complicated build setup, this
@@ -0,0 +1,220 @@
+//! Just enough async to do two things at once without an RTOS.
+//!
+//! There are two implementations of the `embedded-io-async` and
+//! `embedded-hal-async` traits: [`BufferedUartePort`] reads and writes
+//! asynchronously, and [`Delay`] waits on a TIMER. Both are woken from
+//! their interrupt, and [`block_on`] sleeps with `WFE` in between, so the
+//! CPU only runs when there's something to do.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
+use embedded_hal::timer::CountDown;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read, Write};
+use microbit::hal::timer::{self, OneShot, Timer};
+
+use crate::serial::{BufferedUartePort, UarteInterrupt};
+
+/// Where a future that waits for an interrupt leaves its waker, for the
+/// interrupt to wake it.
+pub struct WakerCell(Mutex<RefCell<Option<Waker>>>);
+
+impl WakerCell {
+    pub const fn new() -> Self {
+        WakerCell(Mutex::new(RefCell::new(None)))
+    }
+
+    pub fn register(&self, waker: &Waker) {
+        free(|cs| {
+            let mut cell = self.0.borrow(cs).borrow_mut();
+            match &*cell {
+                Some(old) if old.will_wake(waker) => {}
+                _ => *cell = Some(waker.clone()),
+            }
+        });
+    }
+
+    pub fn wake(&self) {
+        if let Some(waker) = free(|cs| self.0.borrow(cs).borrow_mut().take()) {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for WakerCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> ErrorType
+    for BufferedUartePort<T, RX, TX>
+{
+    type Error = Infallible;
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> Read for BufferedUartePort<T, RX, TX> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        poll_fn(|cx| {
+            // Before looking, so a byte arriving in between still wakes us.
+            self.rx_waker().register(cx.waker());
+            match self.read_available(buf) {
+                0 => Poll::Pending,
+                n => Poll::Ready(Ok(n)),
+            }
+        })
+        .await
+    }
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> Write for BufferedUartePort<T, RX, TX> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        poll_fn(|cx| {
+            self.tx_waker().register(cx.waker());
+            match self.write_available(buf) {
+                0 => Poll::Pending,
+                n => Poll::Ready(Ok(n)),
+            }
+        })
+        .await
+    }
+
+    async fn flush(&mut self) -> Result<(), Infallible> {
+        poll_fn(|cx| {
+            self.tx_waker().register(cx.waker());
+            match embedded_hal::serial::Write::flush(self) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(_) => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+/// Waits on a TIMER, in microsecond steps.
+///
+/// The TIMER's interrupt has to call [`Delay::handle_interrupt`] with the
+/// same waker cell:
+///
+/// ```ignore
+/// static TIMER1_WAKER: WakerCell = WakerCell::new();
+///
+/// let delay = Delay::new(board.TIMER1, &TIMER1_WAKER);
+///
+/// #[interrupt]
+/// fn TIMER1() {
+///     Delay::<TIMER1>::handle_interrupt(&TIMER1_WAKER);
+/// }
+/// ```
+pub struct Delay<T: timer::Instance> {
+    timer: Timer<T, OneShot>,
+    waker: &'static WakerCell,
+}
+
+impl<T: timer::Instance> Delay<T> {
+    pub fn new(timer: T, waker: &'static WakerCell) -> Self {
+        let mut timer = Timer::one_shot(timer);
+        timer.enable_interrupt();
+        Delay { timer, waker }
+    }
+
+    /// Call this from the TIMER's interrupt.
+    pub fn handle_interrupt(waker: &WakerCell) {
+        // The event stays set until the delay sees it, keep the interrupt
+        // from firing over and over until then.
+        NVIC::mask(T::INTERRUPT);
+        waker.wake();
+    }
+}
+
+impl<T: timer::Instance> DelayNs for Delay<T> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay_us(ns.div_ceil(1000)).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        // The TIMER counts at 1 MHz.
+        self.timer.start(us);
+        poll_fn(|cx| {
+            self.waker.register(cx.waker());
+            match self.timer.wait() {
+                Ok(()) => Poll::Ready(()),
+                Err(_) => {
+                    unsafe { NVIC::unmask(T::INTERRUPT) };
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        // One TIMER run lasts up to 71 minutes.
+        for _ in 0..ms / 1_000_000 {
+            self.delay_us(1_000_000_000).await;
+        }
+        self.delay_us(ms % 1_000_000 * 1000).await
+    }
+}
+
+/// Runs both futures until both are done.
+pub async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let mut a = pin!(a);
+    let mut b = pin!(b);
+    let mut a_output = None;
+    let mut b_output = None;
+    poll_fn(|cx| {
+        if a_output.is_none() {
+            if let Poll::Ready(output) = a.as_mut().poll(cx) {
+                a_output = Some(output);
+            }
+        }
+        if b_output.is_none() {
+            if let Poll::Ready(output) = b.as_mut().poll(cx) {
+                b_output = Some(output);
+            }
+        }
+        if a_output.is_some() && b_output.is_some() {
+            Poll::Ready((a_output.take().unwrap(), b_output.take().unwrap()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Runs `future` to completion, sleeping while it waits.
+///
+/// Every wake up polls the whole future again, which is fine for the
+/// handful of things a program here does at once.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // Sleeps until an interrupt or a `wake`. Either one that came
+        // since the poll is remembered, so nothing gets lost.
+        cortex_m::asm::wfe();
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |_| RawWaker::new(core::ptr::null(), &VTABLE),
+    |_| cortex_m::asm::sev(),
+    |_| cortex_m::asm::sev(),
+    |_| {},
+);
@@ -0,0 +1,121 @@
+//! Sleeping until there's something to do, and finding out how much of the
+//! time the CPU actually works.
+//!
+//! A loop like `while !sensor.accel_status().unwrap().xyz_new_data {}`
+//! keeps the CPU busy all the time. [`wait_for`] sleeps with `WFI` in
+//! between instead, and [`Load`] tells how much that saves.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::interrupt;
+use embedded_hal::serial::{Read, Write};
+use microbit::pac::{timer0, TIMER2};
+
+/// Microseconds spent in `WFI`, wrapping around.
+static SLEPT: AtomicU32 = AtomicU32::new(0);
+
+/// Calls `poll` until it returns something, sleeping until the next
+/// interrupt in between.
+///
+/// `poll` runs with interrupts off, so an interrupt that would make it
+/// succeed can't slip in between it and the sleep. It wakes the CPU right
+/// away instead, and runs once `poll` has returned.
+pub fn wait_for<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+    loop {
+        let ready = interrupt::free(|_| {
+            let ready = poll();
+            if ready.is_none() {
+                sleep();
+            }
+            ready
+        });
+        if let Some(ready) = ready {
+            return ready;
+        }
+    }
+}
+
+fn sleep() {
+    let start = now(2);
+    cortex_m::asm::wfi();
+    SLEPT.fetch_add(now(2).wrapping_sub(start), Ordering::Relaxed);
+}
+
+fn timer() -> &'static timer0::RegisterBlock {
+    // Only ever touched here, `Load::new` took it.
+    unsafe { &*TIMER2::ptr() }
+}
+
+/// The time in microseconds, through capture register `cc`. 0 until
+/// there's a [`Load`].
+fn now(cc: usize) -> u32 {
+    timer().tasks_capture[cc].write(|w| unsafe { w.bits(1) });
+    timer().cc[cc].read().bits()
+}
+
+/// How busy the CPU was, from one [`Load::busy`] to the next.
+///
+/// Counts the time spent sleeping in [`wait_for`] on TIMER2. The cycle
+/// counter would be handier, but it stops while the CPU sleeps.
+pub struct Load {
+    time: u32,
+    slept: u32,
+}
+
+impl Load {
+    pub fn new(_timer: TIMER2) -> Self {
+        let timer = timer();
+        timer.mode.write(|w| w.mode().timer());
+        timer.bitmode.write(|w| w.bitmode()._32bit());
+        // 16 MHz / 2^4
+        timer.prescaler.write(|w| unsafe { w.prescaler().bits(4) });
+        timer.tasks_clear.write(|w| unsafe { w.bits(1) });
+        timer.tasks_start.write(|w| unsafe { w.bits(1) });
+        Load {
+            time: now(3),
+            slept: SLEPT.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Percent of the time since the last call that the CPU was awake.
+    /// The calls must be less than 71 minutes apart.
+    pub fn busy(&mut self) -> u32 {
+        let (time, slept) = (now(3), SLEPT.load(Ordering::Relaxed));
+        let total = time.wrapping_sub(self.time) as u64;
+        let asleep = slept.wrapping_sub(self.slept) as u64;
+        self.time = time;
+        self.slept = slept;
+        if total == 0 {
+            return 0;
+        }
+        (100 * total.saturating_sub(asleep) / total) as u32
+    }
+}
+
+/// A serial port that sleeps while there's nothing to read, for
+/// `nb::block!` and the line editors. The port has to raise an interrupt
+/// for every byte it receives, like [`crate::serial::BufferedUartePort`].
+/// Writing goes straight through.
+pub struct Sleeping<'a, S>(pub &'a mut S);
+
+impl<S: Read<u8>> Read<u8> for Sleeping<'_, S> {
+    type Error = S::Error;
+
+    fn read(&mut self) -> nb::Result<u8, S::Error> {
+        wait_for(|| match self.0.read() {
+            Err(nb::Error::WouldBlock) => None,
+            result => Some(result),
+        })
+    }
+}
+
+impl<S: Write<u8>> Write<u8> for Sleeping<'_, S> {
+    type Error = S::Error;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), S::Error> {
+        self.0.write(word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), S::Error> {
+        self.0.flush()
+    }
+}
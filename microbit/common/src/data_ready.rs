@@ -0,0 +1,182 @@
+//! Waking up when the accelerometer has new samples, instead of asking it
+//! over and over.
+//!
+//! The LSM303AGR can pull its INT1 line low whenever there's a new sample,
+//! or once the FIFO holds enough of them. On the micro:bit that line goes
+//! to P0.25, where GPIOTE turns the falling edge into an interrupt.
+//! [`DataReady`] handles it by queueing an event, and the main loop sleeps
+//! in [`Events::wait`] until there is one:
+//!
+//! ```ignore
+//! static EVENTS: Events<4> = Events::new();
+//! static DATA_READY: Mutex<RefCell<Option<DataReady<4>>>> = Mutex::new(RefCell::new(None));
+//!
+//! data_ready::route(&mut i2c, Int1::DataReady).unwrap();
+//! let int = board.pins.p0_25.into_pullup_input().degrade();
+//! let data_ready = DataReady::new(board.GPIOTE, int, &EVENTS);
+//! free(|cs| *DATA_READY.borrow(cs).borrow_mut() = Some(data_ready));
+//! unsafe { NVIC::unmask(pac::Interrupt::GPIOTE) };
+//!
+//! #[interrupt]
+//! fn GPIOTE() {
+//!     free(|cs| {
+//!         if let Some(data_ready) = DATA_READY.borrow(cs).borrow_mut().as_mut() {
+//!             data_ready.handle_interrupt();
+//!         }
+//!     });
+//! }
+//! ```
+//!
+//! The magnetometer can only signal on a pin of its own, [`next_mag`]
+//! looks at it whenever the accelerometer has a sample.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use heapless::Deque;
+use lsm303agr::interface::{ReadData, WriteData};
+use lsm303agr::mode::MagContinuous;
+use lsm303agr::{Error, Lsm303agr, Measurement};
+use microbit::hal::gpio::{Input, Pin, PullUp};
+use microbit::hal::gpiote::Gpiote;
+use microbit::pac::GPIOTE;
+
+use crate::cpu;
+
+const ACCELEROMETER_ADDR: u8 = 0x19;
+
+const CTRL_REG3_A: u8 = 0x22;
+const I1_ZYXDA: u8 = 1 << 4;
+const I1_WTM: u8 = 1 << 2;
+
+const CTRL_REG6_A: u8 = 0x25;
+const H_LACTIVE: u8 = 1 << 1;
+
+/// What pulls INT1 low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Int1 {
+    /// A new sample, until it is read.
+    DataReady,
+    /// The FIFO filling up past its watermark, until it is read, see
+    /// [`crate::fifo`].
+    Watermark,
+}
+
+/// Has the accelerometer signal `source` on INT1, active low since P0.25
+/// is pulled up.
+pub fn route<I, E>(i2c: &mut I, source: Int1) -> Result<(), E>
+where
+    I: Write<Error = E> + WriteRead<Error = E>,
+{
+    let ctrl3 = match source {
+        Int1::DataReady => I1_ZYXDA,
+        Int1::Watermark => I1_WTM,
+    };
+    i2c.write(ACCELEROMETER_ADDR, &[CTRL_REG3_A, ctrl3])?;
+    let mut ctrl6 = [0];
+    i2c.write_read(ACCELEROMETER_ADDR, &[CTRL_REG6_A], &mut ctrl6)?;
+    i2c.write(ACCELEROMETER_ADDR, &[CTRL_REG6_A, ctrl6[0] | H_LACTIVE])
+}
+
+/// What the interrupt leaves for the main loop: the number of the
+/// interrupt, counting up. When the main loop falls behind the oldest ones
+/// get dropped, which shows as a gap.
+pub struct Events<const N: usize>(Mutex<RefCell<Deque<u32, N>>>);
+
+impl<const N: usize> Events<N> {
+    pub const fn new() -> Self {
+        Events(Mutex::new(RefCell::new(Deque::new())))
+    }
+
+    fn push(&self, event: u32) {
+        free(|cs| {
+            let mut events = self.0.borrow(cs).borrow_mut();
+            if events.is_full() {
+                events.pop_front();
+            }
+            // Can't fail, there's room now.
+            let _ = events.push_back(event);
+        });
+    }
+
+    pub fn pop(&self) -> Option<u32> {
+        free(|cs| self.0.borrow(cs).borrow_mut().pop_front())
+    }
+
+    /// Sleeps until there is an event.
+    pub fn wait(&self) -> u32 {
+        cpu::wait_for(|| self.pop())
+    }
+}
+
+impl<const N: usize> Default for Events<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// INT1 on GPIOTE channel 0.
+pub struct DataReady<const N: usize> {
+    gpiote: Gpiote,
+    _int: Pin<Input<PullUp>>,
+    events: &'static Events<N>,
+    count: u32,
+}
+
+impl<const N: usize> DataReady<N> {
+    pub fn new(gpiote: GPIOTE, int: Pin<Input<PullUp>>, events: &'static Events<N>) -> Self {
+        let gpiote = Gpiote::new(gpiote);
+        gpiote
+            .channel0()
+            .input_pin(&int)
+            .hi_to_lo()
+            .enable_interrupt();
+        DataReady {
+            gpiote,
+            _int: int,
+            events,
+            count: 0,
+        }
+    }
+
+    /// Call this from the GPIOTE interrupt.
+    pub fn handle_interrupt(&mut self) {
+        self.gpiote.channel0().reset_events();
+        self.count = self.count.wrapping_add(1);
+        self.events.push(self.count);
+    }
+}
+
+/// Sleeps until the accelerometer has a new sample and returns it. INT1
+/// has to be routed to [`Int1::DataReady`].
+pub fn next_accel<DI, CommE, PinE, MODE, const N: usize>(
+    sensor: &mut Lsm303agr<DI, MODE>,
+    events: &Events<N>,
+) -> Result<Measurement, Error<CommE, PinE>>
+where
+    DI: ReadData<Error = Error<CommE, PinE>> + WriteData<Error = Error<CommE, PinE>>,
+{
+    // An event can be left over from a sample that was already read, and
+    // INT1 stays low for a sample nobody read yet without another event,
+    // so it's the status that counts.
+    while !sensor.accel_status()?.xyz_new_data {
+        events.wait();
+    }
+    sensor.accel_data()
+}
+
+/// Like [`next_accel`], for the magnetometer. It is only checked when the
+/// accelerometer has a sample, so the accelerometer has to run at least
+/// as fast. Its samples in between are thrown away.
+pub fn next_mag<DI, CommE, PinE, const N: usize>(
+    sensor: &mut Lsm303agr<DI, MagContinuous>,
+    events: &Events<N>,
+) -> Result<Measurement, Error<CommE, PinE>>
+where
+    DI: ReadData<Error = Error<CommE, PinE>> + WriteData<Error = Error<CommE, PinE>>,
+{
+    while !sensor.mag_status()?.xyz_new_data {
+        next_accel(sensor, events)?;
+    }
+    sensor.mag_data()
+}
@@ -0,0 +1,167 @@
+//! The accelerometer's FIFO, which the `lsm303agr` driver doesn't cover.
+//!
+//! Reading one sample after the other, each after waiting for
+//! `xyz_new_data`, misses samples as soon as anything else takes longer
+//! than a sample period, 2.5 ms at 400 Hz. In stream mode the sensor
+//! queues up to [`DEPTH`] samples instead, and [`AccelFifo::read`] fetches
+//! all of them in one I2C transaction.
+//!
+//! ```ignore
+//! sensor.set_accel_odr(AccelOutputDataRate::Hz400).unwrap();
+//! let (mode, scale) = (sensor.get_accel_mode(), sensor.get_accel_scale());
+//! let mut fifo = AccelFifo::new(sensor.destroy(), mode, scale, 16).unwrap();
+//! loop {
+//!     let batch = fifo.read().unwrap();
+//!     if batch.overrun {
+//!         rprintln!("too slow, samples got lost");
+//!     }
+//!     for data in &batch.samples {
+//!         // ...
+//!     }
+//! }
+//! ```
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use heapless::Vec;
+use lsm303agr::{AccelMode, AccelScale, Measurement};
+
+const ACCELEROMETER_ADDR: u8 = 0x19;
+
+const CTRL_REG5_A: u8 = 0x24;
+const FIFO_EN: u8 = 1 << 6;
+
+const FIFO_CTRL_REG_A: u8 = 0x2e;
+const MODE_BYPASS: u8 = 0b00 << 6;
+const MODE_STREAM: u8 = 0b10 << 6;
+
+const FIFO_SRC_REG_A: u8 = 0x2f;
+const WTM: u8 = 1 << 7;
+const OVRN_FIFO: u8 = 1 << 6;
+const EMPTY: u8 = 1 << 5;
+const FSS: u8 = 0b1_1111;
+
+const OUT_X_L_A: u8 = 0x28;
+/// Set in the register address to read several registers at once.
+const AUTO_INCREMENT: u8 = 1 << 7;
+
+/// How many samples the FIFO holds.
+pub const DEPTH: usize = 32;
+
+/// The samples that were queued up.
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    /// Oldest first, in mg like `Lsm303agr::accel_data`.
+    pub samples: Vec<Measurement, DEPTH>,
+    /// The FIFO filled up before this read and the oldest samples got
+    /// overwritten, there's a gap before the first one.
+    pub overrun: bool,
+}
+
+/// The accelerometer in stream mode.
+///
+/// Takes the bus, like the driver. Set the output data rate, mode and
+/// scale with the driver first and hand its bus over with
+/// `Lsm303agr::destroy`, or share the bus between the two.
+pub struct AccelFifo<I> {
+    i2c: I,
+    mode: AccelMode,
+    scale: AccelScale,
+}
+
+impl<I, E> AccelFifo<I>
+where
+    I: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Empties the FIFO and starts queueing. `mode` and `scale` are the
+    /// accelerometer's current ones, for turning samples into mg.
+    ///
+    /// The watermark flag is set once more than `watermark` samples are
+    /// queued, at most 31.
+    pub fn new(mut i2c: I, mode: AccelMode, scale: AccelScale, watermark: u8) -> Result<Self, E> {
+        let mut ctrl5 = [0];
+        i2c.write_read(ACCELEROMETER_ADDR, &[CTRL_REG5_A], &mut ctrl5)?;
+        i2c.write(ACCELEROMETER_ADDR, &[CTRL_REG5_A, ctrl5[0] | FIFO_EN])?;
+        // Going through bypass mode throws away whatever was queued.
+        i2c.write(ACCELEROMETER_ADDR, &[FIFO_CTRL_REG_A, MODE_BYPASS])?;
+        i2c.write(
+            ACCELEROMETER_ADDR,
+            &[FIFO_CTRL_REG_A, MODE_STREAM | watermark.min(FSS)],
+        )?;
+        Ok(AccelFifo { i2c, mode, scale })
+    }
+
+    /// Turns the FIFO off again and gives the bus back.
+    pub fn release(mut self) -> Result<I, E> {
+        self.i2c
+            .write(ACCELEROMETER_ADDR, &[FIFO_CTRL_REG_A, MODE_BYPASS])?;
+        let mut ctrl5 = [0];
+        self.i2c
+            .write_read(ACCELEROMETER_ADDR, &[CTRL_REG5_A], &mut ctrl5)?;
+        self.i2c
+            .write(ACCELEROMETER_ADDR, &[CTRL_REG5_A, ctrl5[0] & !FIFO_EN])?;
+        Ok(self.i2c)
+    }
+
+    /// Whether more than the watermark of samples are queued.
+    pub fn watermark_reached(&mut self) -> Result<bool, E> {
+        Ok(self.source()? & WTM != 0)
+    }
+
+    /// Takes everything that is queued, possibly nothing.
+    pub fn read(&mut self) -> Result<Batch, E> {
+        let source = self.source()?;
+        let overrun = source & OVRN_FIFO != 0;
+        // FSS only goes up to 31, a full FIFO also sets OVRN_FIFO.
+        let len = if source & EMPTY != 0 {
+            0
+        } else {
+            (source & FSS) as usize + overrun as usize
+        };
+
+        let mut bytes = [0; DEPTH * 6];
+        let bytes = &mut bytes[..len * 6];
+        if len > 0 {
+            // With the FIFO on, the address goes back from the last output
+            // register to the first one, so this reads sample after sample.
+            self.i2c
+                .write_read(ACCELEROMETER_ADDR, &[OUT_X_L_A | AUTO_INCREMENT], bytes)?;
+        }
+
+        let mut samples = Vec::new();
+        for sample in bytes.chunks_exact(6) {
+            let axis = |i: usize| i16::from_le_bytes([sample[i], sample[i + 1]]);
+            // Can't fail, there are at most DEPTH.
+            let _ = samples.push(Measurement {
+                x: self.scale(axis(0)),
+                y: self.scale(axis(2)),
+                z: self.scale(axis(4)),
+            });
+        }
+        Ok(Batch { samples, overrun })
+    }
+
+    fn source(&mut self) -> Result<u8, E> {
+        let mut source = [0];
+        self.i2c
+            .write_read(ACCELEROMETER_ADDR, &[FIFO_SRC_REG_A], &mut source)?;
+        Ok(source[0])
+    }
+
+    /// A left aligned output register value in mg, the same way
+    /// `Lsm303agr::accel_data` does it.
+    fn scale(&self, raw: i16) -> i32 {
+        let (shift, mg_per_digit) = match self.mode {
+            AccelMode::PowerDown => return 0,
+            AccelMode::HighResolution => (4, 1),
+            AccelMode::Normal => (6, 4),
+            AccelMode::LowPower => (8, 16),
+        };
+        let range = match self.scale {
+            AccelScale::G2 => 1,
+            AccelScale::G4 => 2,
+            AccelScale::G8 => 4,
+            AccelScale::G16 => 8,
+        };
+        (raw / (1 << shift)) as i32 * mg_per_digit * range
+    }
+}
@@ -0,0 +1,114 @@
+//! Finding out what answers on an I2C bus, like `i2cdetect` does on Linux.
+//!
+//! ```ignore
+//! let found = common::i2c::scan(&mut i2c);
+//! uprint!(serial, "{}", found);
+//! ```
+//!
+//! prints
+//!
+//! ```text
+//!      0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+//! 00:                         -- -- -- -- -- -- -- --
+//! 10: -- -- -- -- -- -- -- -- -- 19 -- -- -- -- 1e --
+//! ...
+//! 70: -- -- -- -- -- -- -- --
+//! 19: LSM303AGR accelerometer
+//! 1e: LSM303AGR magnetometer
+//! ```
+
+use core::fmt;
+use embedded_hal::blocking::i2c::Read;
+
+/// The addresses below and above are reserved by the I2C specification,
+/// `i2cdetect` leaves them out as well.
+pub const FIRST_ADDRESS: u8 = 0x08;
+pub const LAST_ADDRESS: u8 = 0x77;
+
+/// Devices found on the micro:bit and the boards people tend to plug into it.
+const KNOWN_DEVICES: &[(u8, &str)] = &[
+    (0x19, "LSM303AGR accelerometer"),
+    (0x1e, "LSM303AGR magnetometer"),
+    (0x70, "KL27 interface chip"),
+];
+
+/// What a device at `address` probably is.
+pub fn known_device(address: u8) -> Option<&'static str> {
+    KNOWN_DEVICES
+        .iter()
+        .find(|(a, _)| *a == address)
+        .map(|(_, name)| *name)
+}
+
+/// The addresses that answered during a [`scan`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Scan {
+    /// Bit n is set if address n answered.
+    found: u128,
+}
+
+impl Scan {
+    pub fn contains(&self, address: u8) -> bool {
+        address < 128 && self.found & 1 << address != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.found == 0
+    }
+
+    /// The addresses that answered, lowest first.
+    pub fn addresses(&self) -> impl Iterator<Item = u8> + '_ {
+        (FIRST_ADDRESS..=LAST_ADDRESS).filter(move |&a| self.contains(a))
+    }
+}
+
+/// Probes every address from [`FIRST_ADDRESS`] to [`LAST_ADDRESS`].
+///
+/// The probe reads a single byte: a device that is there acknowledges its
+/// address and sends whatever it has, without anything being written to
+/// it. Writing, even nothing, can already start a conversion or clear a
+/// flag on some devices.
+pub fn scan<I: Read>(bus: &mut I) -> Scan {
+    let mut found = 0;
+    for address in FIRST_ADDRESS..=LAST_ADDRESS {
+        let mut byte = [0];
+        if bus.read(address, &mut byte).is_ok() {
+            found |= 1 << address;
+        }
+    }
+    Scan { found }
+}
+
+/// The `i2cdetect` grid, followed by a line for every device that is
+/// known, with CRLF line endings for terminals.
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "   ")?;
+        for column in 0..16 {
+            write!(f, "  {:x}", column)?;
+        }
+        write!(f, "\r\n")?;
+        for row in 0..8u8 {
+            write!(f, "{:02x}:", row * 16)?;
+            for column in 0..16 {
+                let address = row * 16 + column;
+                if address < FIRST_ADDRESS {
+                    write!(f, "   ")?;
+                } else if address > LAST_ADDRESS {
+                    break;
+                } else if self.contains(address) {
+                    write!(f, " {:02x}", address)?;
+                } else {
+                    write!(f, " --")?;
+                }
+            }
+            write!(f, "\r\n")?;
+        }
+        for address in self.addresses() {
+            if let Some(name) = known_device(address) {
+                write!(f, "{:02x}: {}\r\n", address, name)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! What the micro:bit chapters share: the board setup, serial ports,
+//! reading lines from them, scanning I2C buses and getting samples from
+//! the LSM303AGR without keeping the CPU busy.
+//!
+//! Depending on this crate also provides `memory.x` for the linker.
+
+#![no_std]
+
+pub use line_editor::LineEditor;
+pub use line_reader::{self, LineReader, Overflow};
+pub use microbit::Board;
+
+pub mod asynch;
+pub mod cpu;
+pub mod data_ready;
+pub mod fifo;
+pub mod i2c;
+pub mod serial;
+
+use microbit::hal::uarte::{self, Instance, Uarte};
+use microbit::pac::{self, NVMC};
+use rtt_target::rtt_init_print;
+
+use crate::serial::{uart_pins, FlowControl};
+
+/// Like `print!`, but to a serial port or anything else that implements
+/// `core::fmt::Write`.
+#[macro_export]
+macro_rules! uprint {
+    ($serial:expr, $($arg:tt)*) => {
+        {
+            use core::fmt::Write as _;
+            $serial.write_fmt(format_args!($($arg)*)).ok()
+        }
+    };
+}
+
+/// Like `println!`, ending the line with CRLF as terminals expect it.
+#[macro_export]
+macro_rules! uprintln {
+    ($serial:expr) => {
+        $crate::uprint!($serial, "\r\n")
+    };
+    ($serial:expr, $fmt:expr) => {
+        $crate::uprint!($serial, concat!($fmt, "\r\n"))
+    };
+    ($serial:expr, $fmt:expr, $($arg:tt)*) => {
+        $crate::uprint!($serial, concat!($fmt, "\r\n"), $($arg)*)
+    };
+}
+
+/// Sets up RTT for `rprintln!` and takes the board's peripherals.
+pub fn init() -> Board {
+    init_with_nvmc().0
+}
+
+/// Like [`init`], and also hands out the NVMC for writing to flash, which
+/// `Board` takes along with everything else but doesn't have a field for.
+pub fn init_with_nvmc() -> (Board, NVMC) {
+    rtt_init_print!();
+    let board = Board::take().unwrap();
+    // `Board::take` only works once and dropped the NVMC, nobody else can
+    // have it.
+    let nvmc = unsafe { pac::Peripherals::steal() }.NVMC;
+    (board, nvmc)
+}
+
+/// The UARTE on `pins`, 115200 baud 8N1 like the terminal settings in the
+/// UART chapter. Hand it to [`serial::UartePort::new`] or
+/// [`serial::BufferedUartePort::new`].
+pub fn uarte<T: Instance>(
+    uarte: T,
+    pins: impl Into<uarte::Pins>,
+    flow_control: Option<FlowControl>,
+) -> Uarte<T> {
+    Uarte::new(
+        uarte,
+        uart_pins(pins, flow_control),
+        uarte::Parity::EXCLUDED,
+        uarte::Baudrate::BAUD115200,
+    )
+}
@@ -0,0 +1,759 @@
+//! Wrappers around the UARTE so it can be used like the micro:bit v1's
+//! `serial`.
+
+use core::convert::Infallible;
+use core::fmt;
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, AtomicBool, AtomicU32, Ordering};
+use cortex_m::peripheral::NVIC;
+use embedded_hal::blocking::serial as bserial;
+use embedded_hal::serial;
+use heapless::spsc::{Consumer, Producer, Queue};
+use microbit::hal::gpio::{Floating, Input, Output, Pin, PushPull};
+use microbit::hal::timer::{self, Instance as _};
+use microbit::hal::uarte::{self, Baudrate, Error, Instance, Parity, Uarte, UarteRx, UarteTx};
+use microbit::pac::{self, PPI, TIMER3, TIMER4, UARTE0, UARTE1};
+
+use crate::asynch::WakerCell;
+
+static mut TX_BUF: [u8; 1] = [0; 1];
+static mut RX_BUF: [u8; 1] = [0; 1];
+
+/// Pins for RTS/CTS flow control, e.g. two of the edge connector's.
+///
+/// With them the UARTE only sends while the other side holds CTS low, and
+/// raises RTS itself when its receive FIFO is about to overflow. Leave CTS
+/// unconnected and nothing gets sent at all.
+pub struct FlowControl {
+    pub cts: Pin<Input<Floating>>,
+    pub rts: Pin<Output<PushPull>>,
+}
+
+/// `board.uart`, with flow control if there are pins for it.
+pub fn uart_pins(pins: impl Into<uarte::Pins>, flow_control: Option<FlowControl>) -> uarte::Pins {
+    let mut pins = pins.into();
+    if let Some(FlowControl { cts, rts }) = flow_control {
+        // The HAL enables HWFC when it gets both.
+        pins.cts = Some(cts);
+        pins.rts = Some(rts);
+    }
+    pins
+}
+
+pub struct UartePort<T: Instance>(UarteTx<T>, UarteRx<T>);
+
+impl<T: Instance> UartePort<T> {
+    pub fn new(serial: Uarte<T>) -> UartePort<T> {
+        let (tx, rx) = serial
+            .split(unsafe { &mut TX_BUF }, unsafe { &mut RX_BUF })
+            .unwrap();
+        UartePort(tx, rx)
+    }
+
+    /// Sets up the UARTE as well, optionally with flow control:
+    ///
+    /// ```ignore
+    /// // Big rings 2 and 1 on the edge connector.
+    /// let flow_control = FlowControl {
+    ///     cts: board.pins.p0_04.into_floating_input().degrade(),
+    ///     rts: board.pins.p0_03.into_push_pull_output(Level::High).degrade(),
+    /// };
+    /// let serial = UartePort::with_pins(
+    ///     board.UARTE0,
+    ///     board.uart,
+    ///     Some(flow_control),
+    ///     Parity::EXCLUDED,
+    ///     Baudrate::BAUD115200,
+    /// );
+    /// ```
+    ///
+    /// Writing blocks while the other side holds CTS high, no bytes are
+    /// dropped.
+    pub fn with_pins(
+        uarte: T,
+        pins: impl Into<uarte::Pins>,
+        flow_control: Option<FlowControl>,
+        parity: Parity,
+        baudrate: Baudrate,
+    ) -> UartePort<T> {
+        UartePort::new(Uarte::new(
+            uarte,
+            uart_pins(pins, flow_control),
+            parity,
+            baudrate,
+        ))
+    }
+}
+
+impl<T: Instance> fmt::Write for UartePort<T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+}
+
+impl<T: Instance> serial::Write<u8> for UartePort<T> {
+    type Error = Error;
+
+    fn write(&mut self, b: u8) -> nb::Result<(), Self::Error> {
+        self.0.write(b)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
+
+impl<T: Instance> bserial::write::Default<u8> for UartePort<T> {}
+
+impl<T: Instance> serial::Read<u8> for UartePort<T> {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.1.read()
+    }
+}
+
+/// Parity bit sent after the 8 data bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParityBit {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// How bytes go over the wire. There are always 8 data bits, the UARTE
+/// can't do anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    baud_rate: u32,
+    parity: ParityBit,
+    stop_bits: StopBits,
+}
+
+impl Framing {
+    /// 115200 baud, 8N1, what the programs start with.
+    pub const DEFAULT: Framing = Framing {
+        baud_rate: 115_200,
+        parity: ParityBit::None,
+        stop_bits: StopBits::One,
+    };
+
+    /// Returns `None` if the UARTE can't do `baud_rate`.
+    pub fn new(baud_rate: u32, parity: ParityBit, stop_bits: StopBits) -> Option<Framing> {
+        baudrate(baud_rate)?;
+        Some(Framing {
+            baud_rate,
+            parity,
+            stop_bits,
+        })
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+}
+
+/// Like `9600 8E1`.
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parity = match self.parity {
+            ParityBit::None => 'N',
+            ParityBit::Even => 'E',
+            ParityBit::Odd => 'O',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        write!(f, "{} 8{}{}", self.baud_rate, parity, stop_bits)
+    }
+}
+
+/// The UARTE's setting for `baud_rate`, if it has one.
+pub fn baudrate(baud_rate: u32) -> Option<Baudrate> {
+    Some(match baud_rate {
+        1200 => Baudrate::BAUD1200,
+        2400 => Baudrate::BAUD2400,
+        4800 => Baudrate::BAUD4800,
+        9600 => Baudrate::BAUD9600,
+        14400 => Baudrate::BAUD14400,
+        19200 => Baudrate::BAUD19200,
+        28800 => Baudrate::BAUD28800,
+        31250 => Baudrate::BAUD31250,
+        38400 => Baudrate::BAUD38400,
+        56000 => Baudrate::BAUD56000,
+        57600 => Baudrate::BAUD57600,
+        76800 => Baudrate::BAUD76800,
+        115200 => Baudrate::BAUD115200,
+        230400 => Baudrate::BAUD230400,
+        250000 => Baudrate::BAUD250000,
+        460800 => Baudrate::BAUD460800,
+        921600 => Baudrate::BAUD921600,
+        1_000_000 => Baudrate::BAUD1M,
+        _ => return None,
+    })
+}
+
+/// What the UARTE is set to, 115200 if it's none of [`baudrate`]'s.
+fn current_baud_rate<T: Instance>(uarte: &T) -> u32 {
+    let bits = uarte.baudrate.read().bits();
+    [
+        1200, 2400, 4800, 9600, 14400, 19200, 28800, 31250, 38400, 56000, 57600, 76800, 115200,
+        230400, 250000, 460800, 921600, 1_000_000,
+    ]
+    .iter()
+    .copied()
+    .find(|&rate| baudrate(rate).map(u32::from) == Some(bits))
+    .unwrap_or(115_200)
+}
+
+/// Most bytes sent in one EasyDMA transfer.
+const TX_CHUNK: usize = 32;
+/// Most bytes received in one EasyDMA transfer.
+const RX_CHUNK: usize = 32;
+
+/// UARTE instances together with their interrupt, and the timer and PPI
+/// channels that end a reception once the line goes quiet.
+pub trait UarteInterrupt: Instance {
+    const INTERRUPT: pac::Interrupt;
+    type IdleTimer: timer::Instance;
+    /// Nothing else may use these, the board doesn't hand out the PPI.
+    const PPI_CHANNELS: [usize; 2];
+}
+
+impl UarteInterrupt for UARTE0 {
+    const INTERRUPT: pac::Interrupt = pac::Interrupt::UARTE0_UART0;
+    type IdleTimer = TIMER3;
+    const PPI_CHANNELS: [usize; 2] = [0, 1];
+}
+
+impl UarteInterrupt for UARTE1 {
+    const INTERRUPT: pac::Interrupt = pac::Interrupt::UARTE1;
+    type IdleTimer = TIMER4;
+    const PPI_CHANNELS: [usize; 2] = [2, 3];
+}
+
+/// Microseconds without a new byte after which the received ones are
+/// handed over, about two bytes with parity and two stop bits.
+fn idle_time(baud_rate: u32) -> u32 {
+    24_000_000 / baud_rate + 1
+}
+
+/// Memory for a [`BufferedUartePort`], it has to live forever because the
+/// UARTE keeps writing to it in the background. Get one with e.g.
+/// `cortex_m::singleton!(: Buffers<64, 64> = Buffers::new()).unwrap()`.
+///
+/// The rings hold `RX - 1` and `TX - 1` bytes.
+pub struct Buffers<const RX: usize, const TX: usize> {
+    rx: Queue<u8, RX>,
+    tx: Queue<u8, TX>,
+    rx_dma: [u8; RX_CHUNK],
+    tx_dma: [u8; TX_CHUNK],
+    shared: Shared,
+}
+
+impl<const RX: usize, const TX: usize> Buffers<RX, TX> {
+    pub const fn new() -> Self {
+        Buffers {
+            rx: Queue::new(),
+            tx: Queue::new(),
+            rx_dma: [0; RX_CHUNK],
+            tx_dma: [0; TX_CHUNK],
+            shared: Shared {
+                tx_idle: AtomicBool::new(true),
+                buffer_overruns: AtomicU32::new(0),
+                hardware_overruns: AtomicU32::new(0),
+                rx_waker: WakerCell::new(),
+                tx_waker: WakerCell::new(),
+            },
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> Default for Buffers<RX, TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the interrupt handler and the port both look at.
+struct Shared {
+    tx_idle: AtomicBool,
+    buffer_overruns: AtomicU32,
+    hardware_overruns: AtomicU32,
+    /// For the async reads and writes in [`crate::asynch`].
+    rx_waker: WakerCell,
+    tx_waker: WakerCell,
+}
+
+/// Received bytes that were lost since the port was created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Overruns {
+    /// Dropped because the receive ring was full, nobody read them in time.
+    pub buffer: u32,
+    /// Lost in the UARTE itself because the interrupt was served too late,
+    /// counted once per overflow of its four byte FIFO.
+    pub hardware: u32,
+}
+
+/// A serial port that receives and transmits in the background.
+///
+/// Unlike [`UartePort`] nothing is lost while the program is busy with
+/// something else: the UARTE interrupt moves received bytes into a ring
+/// buffer and sends whatever was queued for transmission. Reading only
+/// takes bytes out of the ring, writing only puts them in.
+///
+/// Both directions use EasyDMA transfers of up to 32 bytes. A reception
+/// ends when its buffer is full, or when the line has been quiet for about
+/// two bytes: every received byte restarts a timer through PPI, and once
+/// it runs out it stops the UARTE, so a single key press doesn't sit in
+/// the buffer. Bytes arriving until the interrupt starts the next transfer
+/// wait in the UARTE's FIFO. If more than four do, they are lost and show
+/// up in [`Overruns::hardware`].
+///
+/// For flow control create the UARTE with pins from [`uart_pins`]. A
+/// transfer then waits for CTS and the transmit ring fills up behind it,
+/// writing blocks once it is full.
+pub struct BufferedUartePort<T, const RX: usize, const TX: usize> {
+    rx: Consumer<'static, u8, RX>,
+    tx: Producer<'static, u8, TX>,
+    shared: &'static Shared,
+    _uarte: PhantomData<T>,
+}
+
+/// The half of a [`BufferedUartePort`] that lives in the interrupt handler.
+pub struct UarteHandler<T: UarteInterrupt, const RX: usize, const TX: usize> {
+    uarte: T,
+    rx: Producer<'static, u8, RX>,
+    tx: Consumer<'static, u8, TX>,
+    rx_dma: &'static mut [u8; RX_CHUNK],
+    rx_state: RxState,
+    idle: T::IdleTimer,
+    tx_dma: &'static mut [u8; TX_CHUNK],
+    tx_busy: bool,
+    /// STARTTX since the last STOPTX, only then there is a TXSTOPPED to
+    /// wait for.
+    tx_started: bool,
+    tx_stopping: bool,
+    /// What [`UarteHandler::set_framing`] asked for, until it is done.
+    switch_to: Option<Framing>,
+    shared: &'static Shared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RxState {
+    /// Until the buffer is full or the idle timer stops the receiver.
+    Receiving,
+    /// Stopped on purpose to switch the framing, waiting for RXTO.
+    Stopping,
+    /// Moving what's left in the FIFO into the buffer after RXTO.
+    Flushing,
+    /// Until the framing is switched.
+    Stopped,
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> BufferedUartePort<T, RX, TX> {
+    /// Starts receiving right away, `idle` is `board.TIMER3` for UARTE0
+    /// and `board.TIMER4` for UARTE1. The handler has to be called from the
+    /// UARTE's interrupt, which has to be unmasked once the handler is
+    /// where the interrupt can find it:
+    ///
+    /// ```ignore
+    /// static HANDLER: Mutex<RefCell<Option<UarteHandler<UARTE0, 64, 64>>>> = ...;
+    ///
+    /// free(|cs| *HANDLER.borrow(cs).borrow_mut() = Some(handler));
+    /// unsafe { NVIC::unmask(pac::Interrupt::UARTE0_UART0) };
+    ///
+    /// #[interrupt]
+    /// fn UARTE0_UART0() {
+    ///     free(|cs| {
+    ///         if let Some(handler) = HANDLER.borrow(cs).borrow_mut().as_mut() {
+    ///             handler.handle_interrupt();
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn new(
+        serial: Uarte<T>,
+        idle: T::IdleTimer,
+        buffers: &'static mut Buffers<RX, TX>,
+    ) -> (Self, UarteHandler<T, RX, TX>) {
+        let (uarte, _pins) = serial.free();
+        let baud_rate = current_baud_rate(&uarte);
+        let Buffers {
+            rx,
+            tx,
+            rx_dma,
+            tx_dma,
+            shared,
+        } = buffers;
+        let shared: &'static Shared = shared;
+        let (rx_producer, rx_consumer) = rx.split();
+        let (tx_producer, tx_consumer) = tx.split();
+
+        let timer = idle.as_timer0();
+        timer.mode.write(|w| w.mode().timer());
+        timer.bitmode.write(|w| w.bitmode()._32bit());
+        // 16 MHz / 2^4
+        timer.prescaler.write(|w| unsafe { w.prescaler().bits(4) });
+        timer.cc[0].write(|w| unsafe { w.bits(idle_time(baud_rate)) });
+        timer.shorts.write(|w| w.compare0_stop().enabled());
+
+        // Every byte starts the timer over, running out stops the receiver.
+        let [restart, stop] = T::PPI_CHANNELS;
+        let ppi = unsafe { &*PPI::ptr() };
+        ppi.ch[restart]
+            .eep
+            .write(|w| unsafe { w.bits(&uarte.events_rxdrdy as *const _ as u32) });
+        ppi.ch[restart]
+            .tep
+            .write(|w| unsafe { w.bits(&timer.tasks_clear as *const _ as u32) });
+        ppi.fork[restart]
+            .tep
+            .write(|w| unsafe { w.bits(&timer.tasks_start as *const _ as u32) });
+        ppi.ch[stop]
+            .eep
+            .write(|w| unsafe { w.bits(&timer.events_compare[0] as *const _ as u32) });
+        ppi.ch[stop]
+            .tep
+            .write(|w| unsafe { w.bits(&uarte.tasks_stoprx as *const _ as u32) });
+        ppi.chenset.write(|w| unsafe { w.bits(1 << stop) });
+
+        uarte
+            .rxd
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(rx_dma.as_mut_ptr() as u32) });
+        uarte
+            .rxd
+            .maxcnt
+            .write(|w| unsafe { w.maxcnt().bits(RX_CHUNK as _) });
+        uarte.intenset.write(|w| {
+            w.endrx().set();
+            w.rxto().set();
+            w.endtx().set();
+            w.txstopped().set();
+            w.error().set()
+        });
+
+        let port = BufferedUartePort {
+            rx: rx_consumer,
+            tx: tx_producer,
+            shared,
+            _uarte: PhantomData,
+        };
+        let handler = UarteHandler {
+            uarte,
+            rx: rx_producer,
+            tx: tx_consumer,
+            rx_dma,
+            rx_state: RxState::Receiving,
+            idle,
+            tx_dma,
+            tx_busy: false,
+            tx_started: false,
+            tx_stopping: false,
+            switch_to: None,
+            shared,
+        };
+        handler.start_rx();
+        (port, handler)
+    }
+
+    pub fn overruns(&self) -> Overruns {
+        Overruns {
+            buffer: self.shared.buffer_overruns.load(Ordering::Relaxed),
+            hardware: self.shared.hardware_overruns.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn rx_waker(&self) -> &WakerCell {
+        &self.shared.rx_waker
+    }
+
+    pub(crate) fn tx_waker(&self) -> &WakerCell {
+        &self.shared.tx_waker
+    }
+
+    /// Takes as many received bytes as fit into `buf`, without waiting.
+    pub(crate) fn read_available(&mut self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.rx.dequeue() {
+                Some(b) => buf[n] = b,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// Queues as much of `buf` as there's room for, without waiting.
+    pub(crate) fn write_available(&mut self, buf: &[u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() && self.tx.enqueue(buf[n]).is_ok() {
+            n += 1;
+        }
+        if n > 0 {
+            self.start_transmission();
+        }
+        n
+    }
+
+    fn start_transmission(&self) {
+        // The interrupt handler runs to completion before we get here again,
+        // so if it says it is idle it won't pick up the bytes by itself.
+        if self.shared.tx_idle.load(Ordering::Acquire) {
+            NVIC::pend(T::INTERRUPT);
+        }
+    }
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> fmt::Write
+    for BufferedUartePort<T, RX, TX>
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &b in s.as_bytes() {
+            nb::block!(serial::Write::write(self, b)).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> serial::Write<u8>
+    for BufferedUartePort<T, RX, TX>
+{
+    type Error = Infallible;
+
+    /// Queues `b`, blocks only while the transmit ring is full.
+    fn write(&mut self, b: u8) -> nb::Result<(), Self::Error> {
+        let queued = self.tx.enqueue(b);
+        self.start_transmission();
+        queued.map_err(|_| nb::Error::WouldBlock)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.tx.len() != 0 || !self.shared.tx_idle.load(Ordering::Acquire) {
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> bserial::write::Default<u8>
+    for BufferedUartePort<T, RX, TX>
+{
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> serial::Read<u8>
+    for BufferedUartePort<T, RX, TX>
+{
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.rx.dequeue().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<T: UarteInterrupt, const RX: usize, const TX: usize> UarteHandler<T, RX, TX> {
+    /// Call this from the UARTE's interrupt.
+    pub fn handle_interrupt(&mut self) {
+        let uarte = &self.uarte;
+
+        if uarte.events_error.read().bits() != 0 {
+            uarte.events_error.reset();
+            let errors = uarte.errorsrc.read();
+            if errors.overrun().bit_is_set() {
+                self.shared
+                    .hardware_overruns
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            // Writing ones clears them
+            uarte.errorsrc.write(|w| unsafe { w.bits(errors.bits()) });
+        }
+
+        if uarte.events_endrx.read().bits() != 0 {
+            uarte.events_endrx.reset();
+            self.take_received();
+            match self.rx_state {
+                // Unless the timer stopped it, the buffer is full. RXTO
+                // follows otherwise.
+                RxState::Receiving => {
+                    if self.idle.as_timer0().events_compare[0].read().bits() == 0 {
+                        self.start_rx();
+                    }
+                }
+                RxState::Flushing if self.switch_to.is_some() => {
+                    self.rx_state = RxState::Stopped;
+                }
+                RxState::Flushing => {
+                    self.rx_state = RxState::Receiving;
+                    self.start_rx();
+                }
+                RxState::Stopping | RxState::Stopped => {}
+            }
+        }
+
+        let uarte = &self.uarte;
+        if uarte.events_rxto.read().bits() != 0 {
+            uarte.events_rxto.reset();
+            // No more stops until the next transfer has started.
+            self.stop_idle_timer();
+            // Bytes that came in while stopping are still in the FIFO, they
+            // end up in the buffer with another ENDRX.
+            uarte.tasks_flushrx.write(|w| unsafe { w.bits(1) });
+            self.rx_state = RxState::Flushing;
+        }
+
+        let uarte = &self.uarte;
+        if uarte.events_endtx.read().bits() != 0 {
+            uarte.events_endtx.reset();
+            self.tx_busy = false;
+        }
+
+        if uarte.events_txstopped.read().bits() != 0 {
+            uarte.events_txstopped.reset();
+            self.tx_started = false;
+            self.tx_stopping = false;
+        }
+
+        self.switch_framing();
+
+        if !self.tx_busy && !self.tx_stopping {
+            let mut len = 0;
+            while len < TX_CHUNK {
+                match self.tx.dequeue() {
+                    Some(b) => self.tx_dma[len] = b,
+                    None => break,
+                }
+                len += 1;
+            }
+
+            if len == 0 {
+                self.shared.tx_idle.store(true, Ordering::Release);
+            } else {
+                self.shared.tx_idle.store(false, Ordering::Release);
+                self.tx_busy = true;
+                let uarte = &self.uarte;
+                uarte
+                    .txd
+                    .ptr
+                    .write(|w| unsafe { w.ptr().bits(self.tx_dma.as_ptr() as u32) });
+                uarte
+                    .txd
+                    .maxcnt
+                    .write(|w| unsafe { w.maxcnt().bits(len as _) });
+                compiler_fence(Ordering::SeqCst);
+                uarte.tasks_starttx.write(|w| unsafe { w.bits(1) });
+                self.tx_started = true;
+            }
+            // Either there's room in the ring now or everything is out.
+            self.shared.tx_waker.wake();
+        }
+    }
+
+    /// Moves what the last transfer received into the ring.
+    fn take_received(&mut self) {
+        compiler_fence(Ordering::SeqCst);
+        let amount = self.uarte.rxd.amount.read().bits() as usize;
+        for &b in &self.rx_dma[..amount] {
+            if self.rx.enqueue(b).is_err() {
+                self.shared.buffer_overruns.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if amount > 0 {
+            self.shared.rx_waker.wake();
+        }
+    }
+
+    fn start_rx(&self) {
+        // Every byte starts the timer over.
+        let ppi = unsafe { &*PPI::ptr() };
+        ppi.chenset
+            .write(|w| unsafe { w.bits(1 << T::PPI_CHANNELS[0]) });
+        compiler_fence(Ordering::SeqCst);
+        self.uarte.tasks_startrx.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Stops the timer for good, and tells if it ran out and stopped the
+    /// receiver.
+    fn stop_idle_timer(&self) -> bool {
+        let ppi = unsafe { &*PPI::ptr() };
+        ppi.chenclr
+            .write(|w| unsafe { w.bits(1 << T::PPI_CHANNELS[0]) });
+        let timer = self.idle.as_timer0();
+        timer.tasks_stop.write(|w| unsafe { w.bits(1) });
+        let ran_out = timer.events_compare[0].read().bits() != 0;
+        timer.events_compare[0].reset();
+        ran_out
+    }
+
+    /// Switches to another baud rate, parity or stop bits. Everything that
+    /// was queued before goes out with the old ones first, so that e.g. a
+    /// message announcing the switch arrives in one piece.
+    ///
+    /// Returns right away, the interrupt handler finishes the switch once
+    /// the transmit ring is empty and the UARTE has stopped. With flow
+    /// control that waits for the other side to take the queued bytes.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.switch_to = Some(framing);
+        self.switch_framing();
+    }
+
+    /// The next step towards `switch_to`, if there is one.
+    fn switch_framing(&mut self) {
+        let framing = match self.switch_to {
+            Some(framing) => framing,
+            None => return,
+        };
+        if self.tx_busy || self.tx.ready() {
+            return;
+        }
+
+        let uarte = &self.uarte;
+        if self.tx_started && !self.tx_stopping {
+            // The transmitter is done with the DMA buffer but the last byte
+            // might still be on the wire. TXSTOPPED tells when it's out.
+            uarte.tasks_stoptx.write(|w| unsafe { w.bits(1) });
+            self.tx_stopping = true;
+        }
+        if self.rx_state == RxState::Receiving {
+            // Unless the timer beat us to it.
+            if !self.stop_idle_timer() {
+                uarte.tasks_stoprx.write(|w| unsafe { w.bits(1) });
+            }
+            self.rx_state = RxState::Stopping;
+        }
+        if self.tx_started || self.rx_state != RxState::Stopped {
+            return;
+        }
+
+        uarte.enable.write(|w| w.enable().disabled());
+        uarte
+            .baudrate
+            .write(|w| w.baudrate().variant(baudrate(framing.baud_rate).unwrap()));
+        uarte.config.modify(|_, w| {
+            match framing.parity {
+                ParityBit::None => w.parity().excluded(),
+                ParityBit::Even => w.parity().included().paritytype().even(),
+                ParityBit::Odd => w.parity().included().paritytype().odd(),
+            };
+            match framing.stop_bits {
+                StopBits::One => w.stop().one(),
+                StopBits::Two => w.stop().two(),
+            }
+        });
+        uarte.enable.write(|w| w.enable().enabled());
+        self.idle.as_timer0().cc[0].write(|w| unsafe { w.bits(idle_time(framing.baud_rate)) });
+
+        self.switch_to = None;
+        self.rx_state = RxState::Receiving;
+        self.start_rx();
+    }
+}
@@ -3,14 +3,14 @@
 #![no_std]
 
 use cortex_m_rt::entry;
-use microbit::{display::blocking::Display, hal::Timer, Board};
+use microbit::{display::blocking::Display, hal::Timer};
 use panic_rtt_target as _;
-use rtt_target::{rtt_init_print, rprintln};
+#[allow(unused_imports)]
+use rtt_target::rprintln;
 
 #[entry]
 fn main() -> ! {
-    rtt_init_print!();
-    let board = Board::take().unwrap();
+    let board = common::init();
     let mut timer = Timer::new(board.TIMER0);
     let mut display = Display::new(board.display_pins);
 
@@ -0,0 +1,102 @@
+//! Echoes what you type while the LED roulette keeps spinning.
+//!
+//! The blocking echo server can't do this: while it waits in
+//! `nb::block!(serial.read())` nothing else happens, and while the
+//! roulette waits for the next LED the typed bytes pile up. Here both are
+//! `async` and `block_on` runs them side by side, sleeping whenever both
+//! are waiting.
+//!
+//! `cargo embed --example async-echo`
+
+#![no_main]
+#![no_std]
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+use panic_rtt_target as _;
+
+use common::asynch::{block_on, join, Delay, WakerCell};
+use common::serial::{BufferedUartePort, Buffers, UarteHandler};
+use microbit::pac::{self, interrupt, TIMER1, UARTE0};
+
+const RX_SIZE: usize = 64;
+const TX_SIZE: usize = 64;
+
+static SERIAL: Mutex<RefCell<Option<UarteHandler<UARTE0, RX_SIZE, TX_SIZE>>>> =
+    Mutex::new(RefCell::new(None));
+
+static TIMER1_WAKER: WakerCell = WakerCell::new();
+
+#[entry]
+fn main() -> ! {
+    let board = common::init();
+
+    let buffers = cortex_m::singleton!(: Buffers<RX_SIZE, TX_SIZE> = Buffers::new()).unwrap();
+    let (mut serial, handler) = BufferedUartePort::new(
+        common::uarte(board.UARTE0, board.uart, None),
+        board.TIMER3,
+        buffers,
+    );
+    free(|cs| *SERIAL.borrow(cs).borrow_mut() = Some(handler));
+    unsafe { NVIC::unmask(pac::Interrupt::UARTE0_UART0) };
+
+    let mut delay = Delay::new(board.TIMER1, &TIMER1_WAKER);
+    // One LED at a time, so there's no need to multiplex the display.
+    let (mut cols, mut rows) = board.display_pins.degrade();
+
+    let echo = async {
+        let mut buf = [0; 16];
+        loop {
+            // The buffered port can't fail.
+            let n = serial.read(&mut buf).await.unwrap();
+            for &b in &buf[..n] {
+                if b == b'\r' {
+                    serial.write_all(b"\r\n").await.unwrap();
+                } else {
+                    serial.write_all(&[b]).await.unwrap();
+                }
+            }
+        }
+    };
+
+    let roulette = async {
+        for (row, col) in border(5).cycle() {
+            rows[row].set_high().unwrap();
+            cols[col].set_low().unwrap();
+            delay.delay_ms(50).await;
+            rows[row].set_low().unwrap();
+            cols[col].set_high().unwrap();
+        }
+    };
+
+    block_on(join(echo, roulette));
+    unreachable!()
+}
+
+/// The LEDs around the edge of an n by n display, clockwise.
+fn border(n: usize) -> impl Iterator<Item = (usize, usize)> + Clone {
+    let top = (0..n - 1).map(move |j| (0, j));
+    let right = (0..n - 1).map(move |i| (i, n - 1));
+    let bot = (1..n).rev().map(move |j| (n - 1, j));
+    let left = (1..n).rev().map(move |i| (i, 0));
+    top.chain(right).chain(bot).chain(left)
+}
+
+#[interrupt]
+fn UARTE0_UART0() {
+    free(|cs| {
+        if let Some(handler) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            handler.handle_interrupt();
+        }
+    });
+}
+
+#[interrupt]
+fn TIMER1() {
+    Delay::<TIMER1>::handle_interrupt(&TIMER1_WAKER);
+}
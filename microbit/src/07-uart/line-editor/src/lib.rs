@@ -0,0 +1,353 @@
+//! A small line editor for serial consoles like minicom or PuTTY.
+//!
+//! Besides typing and Enter it understands the keys you'd expect from a
+//! shell prompt:
+//!
+//! | Key                  | Effect                                     |
+//! |----------------------|--------------------------------------------|
+//! | Backspace            | delete the character before the cursor     |
+//! | Delete               | delete the character under the cursor      |
+//! | Left / Right         | move the cursor                            |
+//! | Up / Down            | go back and forth through earlier lines    |
+//! | Ctrl-U               | delete everything before the cursor        |
+//! | Ctrl-W               | delete the word before the cursor          |
+//!
+//! The terminal is kept up to date with VT100 escape sequences, which is
+//! what all of them speak. Everything lives in fixed size buffers: lines
+//! are at most `N` bytes and the last `H` of them, at least one, are kept
+//! for Up / Down.
+
+#![no_std]
+
+use embedded_hal::serial::{Read, Write};
+use heapless::{Deque, Vec};
+
+const BELL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const CTRL_U: u8 = 0x15;
+const CTRL_W: u8 = 0x17;
+const ESCAPE: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// Where we are in an escape sequence like `ESC [ A`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// Got `ESC`
+    Start,
+    /// Got `ESC [` or `ESC O`, followed by digits collected in `param`.
+    Sequence,
+}
+
+pub struct LineEditor<const N: usize, const H: usize> {
+    line: Vec<u8, N>,
+    cursor: usize,
+    /// Newest first.
+    history: Deque<Vec<u8, N>, H>,
+    /// Which history entry is shown, `None` while editing a new line.
+    browsing: Option<usize>,
+    /// The new line, put aside while browsing the history.
+    draft: Vec<u8, N>,
+    escape: Escape,
+    param: u8,
+    /// Whether the previous byte was a CR, to swallow the LF of a CRLF.
+    after_cr: bool,
+}
+
+impl<const N: usize, const H: usize> Default for LineEditor<N, H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const H: usize> LineEditor<N, H> {
+    pub fn new() -> Self {
+        LineEditor {
+            line: Vec::new(),
+            cursor: 0,
+            history: Deque::new(),
+            browsing: None,
+            draft: Vec::new(),
+            escape: Escape::None,
+            param: 0,
+            after_cr: false,
+        }
+    }
+
+    /// Waits for a whole line and returns it without the line ending.
+    pub fn read_line<S, E>(&mut self, serial: &mut S) -> Result<Vec<u8, N>, E>
+    where
+        S: Read<u8, Error = E> + Write<u8, Error = E>,
+    {
+        nb::block!(self.poll(serial))
+    }
+
+    /// Handles the bytes that have arrived so far. Returns the line once
+    /// Enter is pressed, `WouldBlock` until then.
+    pub fn poll<S, E>(&mut self, serial: &mut S) -> nb::Result<Vec<u8, N>, E>
+    where
+        S: Read<u8, Error = E> + Write<u8, Error = E>,
+    {
+        loop {
+            let b = serial.read()?;
+            if let Some(line) = self.feed(b, serial)? {
+                nb::block!(serial.flush())?;
+                return Ok(line);
+            }
+            if let Err(nb::Error::Other(e)) = serial.flush() {
+                return Err(nb::Error::Other(e));
+            }
+        }
+    }
+
+    /// Handles one byte of input, echoing to `serial`.
+    fn feed<W, E>(&mut self, b: u8, serial: &mut W) -> Result<Option<Vec<u8, N>>, E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        let after_cr = core::mem::replace(&mut self.after_cr, false);
+
+        match self.escape {
+            Escape::Start => {
+                self.escape = if b == b'[' || b == b'O' {
+                    self.param = 0;
+                    Escape::Sequence
+                } else {
+                    Escape::None
+                };
+                return Ok(None);
+            }
+            Escape::Sequence => {
+                if b.is_ascii_digit() {
+                    self.param = self.param.saturating_mul(10).saturating_add(b - b'0');
+                    return Ok(None);
+                }
+                self.escape = Escape::None;
+                match (b, self.param) {
+                    (b'A', _) => self.older(serial)?,
+                    (b'B', _) => self.newer(serial)?,
+                    (b'C', _) => self.right(serial)?,
+                    (b'D', _) => self.left(serial)?,
+                    (b'~', 3) => self.delete(serial)?,
+                    // Home, End, F keys and so on
+                    _ => {}
+                }
+                return Ok(None);
+            }
+            Escape::None => {}
+        }
+
+        match b {
+            b'\n' if after_cr => {}
+            b'\r' | b'\n' => {
+                self.after_cr = b == b'\r';
+                return self.enter(serial).map(Some);
+            }
+            ESCAPE => self.escape = Escape::Start,
+            BACKSPACE | DEL => self.backspace(serial)?,
+            CTRL_U => {
+                let n = self.cursor;
+                self.remove_before(n, serial)?;
+            }
+            CTRL_W => {
+                let before = &self.line[..self.cursor];
+                let spaces = before.iter().rev().take_while(|&&c| c == b' ').count();
+                let word = before[..before.len() - spaces]
+                    .iter()
+                    .rev()
+                    .take_while(|&&c| c != b' ')
+                    .count();
+                self.remove_before(spaces + word, serial)?;
+            }
+            0x20..=0x7e => self.insert(b, serial)?,
+            // Other control characters
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn enter<W, E>(&mut self, serial: &mut W) -> Result<Vec<u8, N>, E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        write_all(serial, b"\r\n")?;
+        let line = core::mem::take(&mut self.line);
+        self.cursor = 0;
+        self.browsing = None;
+        self.draft.clear();
+
+        if !line.is_empty() && self.history.front() != Some(&line) {
+            if self.history.is_full() {
+                self.history.pop_back();
+            }
+            let _ = self.history.push_front(line.clone());
+        }
+        Ok(line)
+    }
+
+    fn insert<W, E>(&mut self, b: u8, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        if self.line.len() == N {
+            return write_all(serial, &[BELL]);
+        }
+        // There's room, so this can't fail.
+        let _ = self.line.insert(self.cursor, b);
+        self.cursor += 1;
+        write_all(serial, &self.line[self.cursor - 1..])?;
+        cursor_left(serial, self.line.len() - self.cursor)
+    }
+
+    fn backspace<W, E>(&mut self, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        self.remove_before(1.min(self.cursor), serial)
+    }
+
+    fn delete<W, E>(&mut self, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        if self.cursor == self.line.len() {
+            return Ok(());
+        }
+        self.line.remove(self.cursor);
+        self.redraw_tail(1, serial)
+    }
+
+    /// Removes `n` characters before the cursor.
+    fn remove_before<W, E>(&mut self, n: usize, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        if n == 0 {
+            return Ok(());
+        }
+        let start = self.cursor - n;
+        let len = self.line.len();
+        self.line.copy_within(self.cursor..len, start);
+        self.line.truncate(len - n);
+        cursor_left(serial, n)?;
+        self.cursor = start;
+        self.redraw_tail(n, serial)
+    }
+
+    /// Redraws the line from the cursor to the end and blanks the `removed`
+    /// characters after it that are now gone.
+    fn redraw_tail<W, E>(&mut self, removed: usize, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        write_all(serial, &self.line[self.cursor..])?;
+        for _ in 0..removed {
+            write_all(serial, b" ")?;
+        }
+        cursor_left(serial, self.line.len() - self.cursor + removed)
+    }
+
+    fn left<W, E>(&mut self, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            cursor_left(serial, 1)?;
+        }
+        Ok(())
+    }
+
+    fn right<W, E>(&mut self, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        if self.cursor < self.line.len() {
+            self.cursor += 1;
+            write_all(serial, b"\x1b[C")?;
+        }
+        Ok(())
+    }
+
+    fn older<W, E>(&mut self, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        let index = match self.browsing {
+            None if !self.history.is_empty() => {
+                self.draft = self.line.clone();
+                0
+            }
+            Some(i) if i + 1 < self.history.len() => i + 1,
+            _ => return write_all(serial, &[BELL]),
+        };
+        self.browsing = Some(index);
+        let line = self.history.iter().nth(index).cloned().unwrap_or_default();
+        self.replace(line, serial)
+    }
+
+    fn newer<W, E>(&mut self, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        let line = match self.browsing {
+            None => return write_all(serial, &[BELL]),
+            Some(0) => {
+                self.browsing = None;
+                core::mem::take(&mut self.draft)
+            }
+            Some(i) => {
+                self.browsing = Some(i - 1);
+                self.history.iter().nth(i - 1).cloned().unwrap_or_default()
+            }
+        };
+        self.replace(line, serial)
+    }
+
+    /// Shows `line` instead of the current one, with the cursor at its end.
+    fn replace<W, E>(&mut self, line: Vec<u8, N>, serial: &mut W) -> Result<(), E>
+    where
+        W: Write<u8, Error = E>,
+    {
+        cursor_left(serial, self.cursor)?;
+        self.line = line;
+        self.cursor = self.line.len();
+        write_all(serial, &self.line)?;
+        // Erase whatever is left of the longer old line.
+        write_all(serial, b"\x1b[K")
+    }
+}
+
+fn write_all<W, E>(serial: &mut W, bytes: &[u8]) -> Result<(), E>
+where
+    W: Write<u8, Error = E>,
+{
+    for &b in bytes {
+        nb::block!(serial.write(b))?;
+    }
+    Ok(())
+}
+
+/// Moves the terminal's cursor `n` columns to the left.
+fn cursor_left<W, E>(serial: &mut W, n: usize) -> Result<(), E>
+where
+    W: Write<u8, Error = E>,
+{
+    match n {
+        0 => Ok(()),
+        1 => write_all(serial, &[BACKSPACE]),
+        _ => {
+            let mut digits = [0; 20];
+            let mut i = digits.len();
+            let mut n = n;
+            while n > 0 {
+                i -= 1;
+                digits[i] = b'0' + (n % 10) as u8;
+                n /= 10;
+            }
+            write_all(serial, b"\x1b[")?;
+            write_all(serial, &digits[i..])?;
+            write_all(serial, b"D")
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Types scripted keystrokes into the editor and checks both the returned
+//! line and what a terminal would show after replaying the echo.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+
+use embedded_hal::serial::{Read, Write};
+use line_editor::LineEditor;
+
+const LEFT: &str = "\x1b[D";
+const RIGHT: &str = "\x1b[C";
+const UP: &str = "\x1b[A";
+const DOWN: &str = "\x1b[B";
+const DELETE: &str = "\x1b[3~";
+const BACKSPACE: &str = "\x7f";
+const CTRL_U: &str = "\x15";
+const CTRL_W: &str = "\x17";
+
+/// Serial port that plays back `input` and records everything written.
+#[derive(Default)]
+struct FakePort {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl FakePort {
+    fn typing(keys: &str) -> FakePort {
+        FakePort {
+            input: keys.bytes().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    fn type_more(&mut self, keys: &str) {
+        self.input.extend(keys.bytes());
+    }
+}
+
+impl Read<u8> for FakePort {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.input.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl Write<u8> for FakePort {
+    type Error = Infallible;
+
+    fn write(&mut self, b: u8) -> nb::Result<(), Infallible> {
+        self.output.push(b);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Replays the echo of the current line on a one line VT100 screen and
+/// returns what's visible with trailing blanks removed, plus the cursor.
+fn screen(output: &[u8]) -> (String, usize) {
+    let mut cells: Vec<u8> = Vec::new();
+    let mut cursor = 0;
+    let mut i = 0;
+    while i < output.len() {
+        match output[i] {
+            b'\r' => cursor = 0,
+            b'\n' => cells.clear(),
+            0x07 => {}
+            0x08 => cursor -= 1,
+            0x1b => {
+                assert_eq!(output[i + 1], b'[');
+                let mut j = i + 2;
+                while output[j].is_ascii_digit() {
+                    j += 1;
+                }
+                let n: usize = std::str::from_utf8(&output[i + 2..j])
+                    .unwrap()
+                    .parse()
+                    .unwrap_or(1);
+                match output[j] {
+                    b'C' => cursor += n,
+                    b'D' => cursor = cursor.checked_sub(n).expect("cursor left of line"),
+                    b'K' => cells.truncate(cursor),
+                    c => panic!("unexpected escape {:?}", c as char),
+                }
+                i = j;
+            }
+            c => {
+                if cursor == cells.len() {
+                    cells.push(c);
+                } else {
+                    cells[cursor] = c;
+                }
+                cursor += 1;
+            }
+        }
+        i += 1;
+    }
+    let text = String::from_utf8(cells).unwrap();
+    (text.trim_end().to_string(), cursor)
+}
+
+fn line(editor: &mut LineEditor<32, 4>, port: &mut FakePort) -> String {
+    let line = editor.read_line(port).unwrap();
+    String::from_utf8(line.to_vec()).unwrap()
+}
+
+/// Types `keys` without Enter and returns what the screen shows.
+fn edit(keys: &str) -> (String, usize) {
+    let mut editor = LineEditor::<32, 4>::new();
+    let mut port = FakePort::typing(keys);
+    assert!(matches!(editor.poll(&mut port), Err(nb::Error::WouldBlock)));
+    screen(&port.output)
+}
+
+#[test]
+fn plain_line() {
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing("hello\r");
+    assert_eq!(line(&mut editor, &mut port), "hello");
+    assert_eq!(port.output, b"hello\r\n");
+}
+
+#[test]
+fn line_endings() {
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing("a\rb\nc\r\nd\r\n");
+    for expected in ["a", "b", "c", "d"] {
+        assert_eq!(line(&mut editor, &mut port), expected);
+    }
+    // The LF after the last CR is swallowed, not taken as an empty line.
+    assert!(matches!(editor.poll(&mut port), Err(nb::Error::WouldBlock)));
+    assert!(port.input.is_empty());
+}
+
+#[test]
+fn empty_line() {
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing("\r");
+    assert_eq!(line(&mut editor, &mut port), "");
+}
+
+#[test]
+fn waits_for_enter() {
+    let mut editor = LineEditor::<32, 4>::new();
+    let mut port = FakePort::typing("hel");
+    assert!(matches!(editor.poll(&mut port), Err(nb::Error::WouldBlock)));
+    port.type_more("lo\r");
+    assert_eq!(editor.poll(&mut port).ok().unwrap().as_slice(), b"hello");
+}
+
+#[test]
+fn backspace() {
+    assert_eq!(
+        edit(&format!("helo{}{}llo", BACKSPACE, BACKSPACE)),
+        ("hello".into(), 5)
+    );
+    assert_eq!(edit("a\x08b"), ("b".into(), 1));
+    // Nothing to delete
+    assert_eq!(edit(&format!("{}x", BACKSPACE)), ("x".into(), 1));
+}
+
+#[test]
+fn backspace_in_the_middle() {
+    let keys = format!("hexllo{}{}{}{}", LEFT, LEFT, LEFT, BACKSPACE);
+    assert_eq!(edit(&keys), ("hello".into(), 2));
+}
+
+#[test]
+fn delete() {
+    let keys = format!("hexllo{}{}{}{}{}", LEFT, LEFT, LEFT, LEFT, DELETE);
+    assert_eq!(edit(&keys), ("hello".into(), 2));
+    // At the end nothing happens
+    assert_eq!(edit(&format!("ab{}", DELETE)), ("ab".into(), 2));
+}
+
+#[test]
+fn insert_in_the_middle() {
+    let keys = format!("hllo{}{}{}e", LEFT, LEFT, LEFT);
+    assert_eq!(edit(&keys), ("hello".into(), 2));
+
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing(&format!("{}\r", keys));
+    assert_eq!(line(&mut editor, &mut port), "hello");
+}
+
+#[test]
+fn cursor_stays_inside_line() {
+    let keys = format!("ab{}{}{}x{}{}{}y", LEFT, LEFT, LEFT, RIGHT, RIGHT, RIGHT);
+    assert_eq!(edit(&keys), ("xaby".into(), 4));
+}
+
+#[test]
+fn ctrl_u() {
+    assert_eq!(edit(&format!("hello{}", CTRL_U)), ("".into(), 0));
+    let keys = format!("say hello{}{}{}{}{}", LEFT, LEFT, LEFT, LEFT, LEFT);
+    assert_eq!(edit(&format!("{}{}", keys, CTRL_U)), ("hello".into(), 0));
+}
+
+#[test]
+fn ctrl_w() {
+    assert_eq!(edit(&format!("i2c read  {}", CTRL_W)), ("i2c".into(), 4));
+    assert_eq!(
+        edit(&format!("i2c read{}{}", CTRL_W, CTRL_W)),
+        ("".into(), 0)
+    );
+    let keys = format!(
+        "one two three{}{}{}{}{}{}",
+        LEFT, LEFT, LEFT, LEFT, LEFT, LEFT
+    );
+    assert_eq!(
+        edit(&format!("{}{}", keys, CTRL_W)),
+        ("one  three".into(), 4)
+    );
+}
+
+#[test]
+fn full_line_rings_the_bell() {
+    let mut editor = LineEditor::<4, 1>::new();
+    let mut port = FakePort::typing("abcdef\r");
+    let line = editor.read_line(&mut port).unwrap();
+    assert_eq!(line.as_slice(), b"abcd");
+    assert_eq!(port.output, b"abcd\x07\x07\r\n");
+}
+
+#[test]
+fn history() {
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing("first\rsecond\r");
+    line(&mut editor, &mut port);
+    line(&mut editor, &mut port);
+
+    port.output.clear();
+    port.type_more(&format!("{}{}\r", UP, UP));
+    assert_eq!(line(&mut editor, &mut port), "first");
+
+    port.type_more(&format!("{}\r", UP));
+    assert_eq!(line(&mut editor, &mut port), "first");
+
+    port.type_more(&format!("{}{}{}\r", UP, UP, DOWN));
+    assert_eq!(line(&mut editor, &mut port), "first");
+}
+
+#[test]
+fn history_keeps_draft() {
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing("a much longer line\r");
+    line(&mut editor, &mut port);
+
+    port.output.clear();
+    port.type_more(&format!("new{}", UP));
+    assert!(matches!(editor.poll(&mut port), Err(nb::Error::WouldBlock)));
+    assert_eq!(screen(&port.output), ("a much longer line".into(), 18));
+
+    port.type_more(DOWN);
+    assert!(matches!(editor.poll(&mut port), Err(nb::Error::WouldBlock)));
+    assert_eq!(screen(&port.output), ("new".into(), 3));
+
+    port.type_more("\r");
+    assert_eq!(line(&mut editor, &mut port), "new");
+}
+
+#[test]
+fn history_drops_oldest() {
+    let mut editor = LineEditor::<32, 2>::new();
+    let mut port = FakePort::typing("one\rtwo\rthree\r");
+    for _ in 0..3 {
+        editor.read_line(&mut port).unwrap();
+    }
+    port.type_more(&format!("{}{}{}\r", UP, UP, UP));
+    assert_eq!(editor.read_line(&mut port).unwrap().as_slice(), b"two");
+}
+
+#[test]
+fn history_skips_empty_and_repeated() {
+    let mut editor = LineEditor::new();
+    let mut port = FakePort::typing("one\rtwo\rtwo\r\r");
+    for _ in 0..4 {
+        line(&mut editor, &mut port);
+    }
+    port.type_more(&format!("{}{}\r", UP, UP));
+    assert_eq!(line(&mut editor, &mut port), "one");
+}
+
+#[test]
+fn unknown_escapes_are_ignored() {
+    // Home, End and F1
+    assert_eq!(edit("a\x1b[H\x1b[4~\x1bOPb"), ("ab".into(), 2));
+}
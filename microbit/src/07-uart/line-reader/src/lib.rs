@@ -0,0 +1,113 @@
+//! Collects bytes from a serial port into lines of at most `N` bytes.
+//!
+//! Unlike the line editor there's no echo and no editing, which makes it
+//! fit for programs talking to other programs, and for the plain echo
+//! servers of the UART chapters. Lines end with CR, LF or CRLF, and what
+//! happens to longer lines than fit is up to the [`Overflow`] policy.
+
+#![no_std]
+
+use core::convert::Infallible;
+use core::fmt;
+
+use embedded_hal::serial::Read;
+use heapless::Vec;
+
+/// What to do with a line longer than `N` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail with [`Error::TooLong`] right away. The byte that didn't fit is
+    /// lost, what comes after it is read as the next line.
+    Error,
+    /// Keep the first `N` bytes and return them at the end of the line.
+    Truncate,
+    /// Drop the whole line and fail with [`Error::TooLong`] once it ends,
+    /// so the next line starts clean.
+    Discard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E = Infallible> {
+    /// The line didn't fit.
+    TooLong,
+    /// Reading from the serial port failed.
+    Serial(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TooLong => f.write_str("line too long"),
+            Error::Serial(e) => write!(f, "serial error: {:?}", e),
+        }
+    }
+}
+
+pub struct LineReader<const N: usize> {
+    line: Vec<u8, N>,
+    overflow: Overflow,
+    /// Whether the current line didn't fit.
+    overflowed: bool,
+    /// Whether the previous byte was a CR, to swallow the LF of a CRLF.
+    after_cr: bool,
+}
+
+impl<const N: usize> LineReader<N> {
+    pub const fn new(overflow: Overflow) -> Self {
+        LineReader {
+            line: Vec::new(),
+            overflow,
+            overflowed: false,
+            after_cr: false,
+        }
+    }
+
+    /// Waits for a whole line and returns it without the line ending.
+    pub fn read_line<S: Read<u8>>(
+        &mut self,
+        serial: &mut S,
+    ) -> Result<Vec<u8, N>, Error<S::Error>> {
+        loop {
+            let b = nb::block!(serial.read()).map_err(Error::Serial)?;
+            if let Some(line) = self.push(b) {
+                return line.map_err(|_| Error::TooLong);
+            }
+        }
+    }
+
+    /// Handles one byte. Returns the line once it ends, `None` until then.
+    pub fn push(&mut self, b: u8) -> Option<Result<Vec<u8, N>, Error>> {
+        let after_cr = core::mem::replace(&mut self.after_cr, false);
+        match b {
+            b'\n' if after_cr => None,
+            b'\r' | b'\n' => {
+                self.after_cr = b == b'\r';
+                let line = core::mem::take(&mut self.line);
+                if core::mem::replace(&mut self.overflowed, false) {
+                    match self.overflow {
+                        Overflow::Truncate => Some(Ok(line)),
+                        _ => Some(Err(Error::TooLong)),
+                    }
+                } else {
+                    Some(Ok(line))
+                }
+            }
+            _ if self.overflowed => None,
+            _ => {
+                if self.line.push(b).is_ok() {
+                    return None;
+                }
+                match self.overflow {
+                    Overflow::Error => {
+                        self.line.clear();
+                        Some(Err(Error::TooLong))
+                    }
+                    Overflow::Truncate | Overflow::Discard => {
+                        self.overflowed = true;
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Feeds canned input to the reader, once per overflow policy.
+
+use std::collections::VecDeque;
+
+use embedded_hal::serial::Read;
+use line_reader::{Error, LineReader, Overflow};
+
+/// Every line, or error, that `input` produces.
+fn lines<const N: usize>(overflow: Overflow, input: &str) -> Vec<Result<String, Error>> {
+    let mut reader = LineReader::<N>::new(overflow);
+    input
+        .bytes()
+        .filter_map(|b| reader.push(b))
+        .map(|line| line.map(|line| String::from_utf8(line.to_vec()).unwrap()))
+        .collect()
+}
+
+fn ok(line: &str) -> Result<String, Error> {
+    Ok(line.to_string())
+}
+
+#[test]
+fn line_endings() {
+    assert_eq!(
+        lines::<8>(Overflow::Error, "cr\rlf\ncrlf\r\n\n\r"),
+        [ok("cr"), ok("lf"), ok("crlf"), ok(""), ok("")]
+    );
+}
+
+#[test]
+fn exactly_full() {
+    for &overflow in &[Overflow::Error, Overflow::Truncate, Overflow::Discard] {
+        assert_eq!(lines::<4>(overflow, "abcd\r"), [ok("abcd")]);
+    }
+}
+
+#[test]
+fn error() {
+    // Fails on the sixth byte and drops it, the rest is the next line.
+    assert_eq!(
+        lines::<5>(Overflow::Error, "too long\r\nok\r\n"),
+        [Err(Error::TooLong), ok("ng"), ok("ok")]
+    );
+}
+
+#[test]
+fn truncate() {
+    assert_eq!(
+        lines::<5>(Overflow::Truncate, "too long\r\nok\r\n"),
+        [ok("too l"), ok("ok")]
+    );
+}
+
+#[test]
+fn discard() {
+    assert_eq!(
+        lines::<5>(Overflow::Discard, "too long\r\nok\r\nmuch too long\nok\n"),
+        [Err(Error::TooLong), ok("ok"), Err(Error::TooLong), ok("ok")]
+    );
+}
+
+/// Has `input`, then fails.
+struct FakePort {
+    input: VecDeque<u8>,
+}
+
+impl Read<u8> for FakePort {
+    type Error = &'static str;
+
+    fn read(&mut self) -> nb::Result<u8, &'static str> {
+        self.input.pop_front().ok_or(nb::Error::Other("unplugged"))
+    }
+}
+
+#[test]
+fn read_line() {
+    let mut port = FakePort {
+        input: b"hello\r\ntoo long\r\nbye".iter().copied().collect(),
+    };
+    let mut reader = LineReader::<5>::new(Overflow::Discard);
+    assert_eq!(reader.read_line(&mut port).unwrap(), b"hello"[..]);
+    assert_eq!(reader.read_line(&mut port), Err(Error::TooLong));
+    assert_eq!(reader.read_line(&mut port), Err(Error::Serial("unplugged")));
+}
@@ -9,29 +9,42 @@ use heapless::Vec;
 use rtt_target::{rtt_init_print, rprintln, rprint};
 use panic_rtt_target as _;
 use core::fmt::{Write, Display, self, Debug};
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
 
 use microbit::{
     hal::prelude::*,
     hal::uarte,
     hal::uarte::{Baudrate, Parity},
+    pac::{self, interrupt, UARTE0},
 };
 
-mod serial_setup;
-use serial_setup::UartePort;
+use common::serial::{BufferedUartePort, Buffers, Overruns, UarteHandler, UartePort};
+use common::{uprint, uprintln, LineEditor};
+
+/// Size of the receive and transmit rings.
+const RX_SIZE: usize = 64;
+const TX_SIZE: usize = 64;
+
+/// Longest line, and how many of them Up / Down can bring back.
+const LINE_SIZE: usize = 32;
+const HISTORY: usize = 8;
+
+static SERIAL: Mutex<RefCell<Option<UarteHandler<UARTE0, RX_SIZE, TX_SIZE>>>> =
+    Mutex::new(RefCell::new(None));
 
 #[entry]
 fn main() -> ! {
-    rtt_init_print!();
-    let board = microbit::Board::take().unwrap();
+    let board = common::init();
 
     let mut serial = {
-        let serial = uarte::Uarte::new(
-            board.UARTE0,
-            board.uart.into(),
-            Parity::EXCLUDED,
-            Baudrate::BAUD115200,
-        );
-        UartePort::new(serial)
+        let serial = common::uarte(board.UARTE0, board.uart, None);
+        let buffers = cortex_m::singleton!(: Buffers<RX_SIZE, TX_SIZE> = Buffers::new()).unwrap();
+        let (port, handler) = BufferedUartePort::new(serial, board.TIMER3, buffers);
+        free(|cs| *SERIAL.borrow(cs).borrow_mut() = Some(handler));
+        unsafe { NVIC::unmask(pac::Interrupt::UARTE0_UART0) };
+        port
     };
 
     // let mut buffer = Vec::<u8, 32>::new();
@@ -57,23 +70,25 @@ fn main() -> ! {
     //     nb::block!(serial.flush()).unwrap();
     // }
 
+    let mut editor = LineEditor::<LINE_SIZE, HISTORY>::new();
+    let mut lost = Overruns::default();
     loop {
         // rprintln!("top of loop");
 
-        match read_line(&mut serial) {
-            Ok(buf) => {
-                for &b in buf.iter().rev() {
-                // for &b in buf.as_slice().into_iter().rev() {
-                    write!(serial, "{}", b as char).expect("write serial");
-                }
-                write!(serial, "\r\n").expect("write serial");
-            }
-            Err(e) => {
-                write!(serial, "{e}\r\n").expect("write serial");
-            }
+        // The buffered port can't fail.
+        let buf = editor.read_line(&mut serial).unwrap();
+        for &b in buf.iter().rev() {
+        // for &b in buf.as_slice().into_iter().rev() {
+            uprint!(serial, "{}", b as char);
         }
+        uprintln!(serial);
 
         nb::block!(serial.flush()).unwrap();
+
+        if serial.overruns() != lost {
+            lost = serial.overruns();
+            rprintln!("lost bytes: {:?}", lost);
+        }
     }
 
     // writeln!(serial, "The quick brown fox jumps over the lazy dog.").unwrap();
@@ -97,17 +112,11 @@ fn main() -> ! {
     // loop {}
 }
 
-fn read_line<R>(serial: &mut R) -> Result<Vec<u8, 32>, &'static str>
-where
-    R: serial::Read<u8>,
-    <R as serial::Read<u8>>::Error: Debug + Send + Sync,
-{
-    let mut buf = Vec::<u8, 32>::new();
-    loop {
-        let b = nb::block!(serial.read()).map_err(|_| "serial read")?;
-        if b == b'\r' || b == b'\n' {
-            return Ok(buf);
+#[interrupt]
+fn UARTE0_UART0() {
+    free(|cs| {
+        if let Some(handler) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            handler.handle_interrupt();
         }
-        buf.push(b).map_err(|_| "buf full")?;
-    }
+    });
 }
@@ -0,0 +1,93 @@
+//! Turns the binary sample stream of the `stream` command into CSV and
+//! keeps track of frames that got lost or corrupted on the way.
+
+use std::io::{self, Write};
+
+use sensor_stream::{Decoder, Sample};
+
+pub mod serial;
+
+pub const CSV_HEADER: &str = "sequence,timestamp_us,sensor,x,y,z";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Decoded and written out.
+    pub samples: u64,
+    /// Missing from the sequence, including the corrupted ones.
+    pub dropped: u64,
+    /// Failed to decode.
+    pub corrupted: u64,
+}
+
+pub struct Recorder<W, L> {
+    decoder: Decoder,
+    csv: W,
+    /// Where drops and errors are reported as they happen.
+    log: L,
+    last_sequence: Option<u16>,
+    stats: Stats,
+}
+
+impl<W: Write, L: Write> Recorder<W, L> {
+    pub fn new(mut csv: W, log: L) -> io::Result<Recorder<W, L>> {
+        writeln!(csv, "{}", CSV_HEADER)?;
+        Ok(Recorder {
+            decoder: Decoder::new(),
+            csv,
+            log,
+            last_sequence: None,
+            stats: Stats::default(),
+        })
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for &b in bytes {
+            match self.decoder.push(b) {
+                Some(Ok(sample)) => self.sample(&sample)?,
+                Some(Err(e)) => {
+                    self.stats.corrupted += 1;
+                    writeln!(self.log, "bad frame: {}", e)?;
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn sample(&mut self, sample: &Sample) -> io::Result<()> {
+        if let Some(last) = self.last_sequence {
+            let missing = sample.sequence.wrapping_sub(last.wrapping_add(1));
+            if missing > 0 {
+                self.stats.dropped += missing as u64;
+                writeln!(
+                    self.log,
+                    "dropped {} frame(s) before #{}",
+                    missing, sample.sequence
+                )?;
+            }
+        }
+        self.last_sequence = Some(sample.sequence);
+        self.stats.samples += 1;
+
+        writeln!(
+            self.csv,
+            "{},{},{},{},{},{}",
+            sample.sequence,
+            sample.timestamp,
+            sample.sensor.name(),
+            sample.x,
+            sample.y,
+            sample.z
+        )
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Flushes the CSV and returns the final numbers.
+    pub fn finish(mut self) -> io::Result<Stats> {
+        self.csv.flush()?;
+        Ok(self.stats)
+    }
+}
@@ -0,0 +1,90 @@
+//! Records the board's binary sample stream as CSV on stdout.
+//!
+//! ```text
+//! recorder /dev/ttyACM0 --send "stream both 100 bin" > samples.csv
+//! recorder capture.bin > samples.csv
+//! ```
+//!
+//! Dropped and corrupted frames are reported on stderr as they happen,
+//! and counted up at the end.
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
+
+use recorder::{serial, Recorder};
+
+const USAGE: &str = "usage: recorder <serial port or file> [--baud <rate>] [--send <command>]";
+
+struct Options {
+    path: PathBuf,
+    baud: u32,
+    /// Typed into the board's console before recording, e.g. `stream both 100 bin`.
+    send: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut baud = 115200;
+    let mut send = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--baud" => {
+                let value = args.next().ok_or("--baud needs a rate")?;
+                baud = value
+                    .parse()
+                    .map_err(|_| format!("invalid baud rate {:?}", value))?;
+            }
+            "--send" => send = Some(args.next().ok_or("--send needs a command")?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {:?}\n{}", arg, USAGE)),
+        }
+    }
+    Ok(Options {
+        path: path.ok_or(USAGE)?,
+        baud,
+        send,
+    })
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let mut port = serial::open(&options.path, options.baud)?;
+    if let Some(command) = &options.send {
+        write!(port, "{}\r", command)?;
+    }
+
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    let mut recorder = Recorder::new(stdout.lock(), stderr.lock())?;
+    let mut buffer = [0; 1024];
+    loop {
+        let n = match port.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if serial::is_hangup(&e) => break,
+            Err(e) => return Err(e),
+        };
+        recorder.feed(&buffer[..n])?;
+    }
+
+    let stats = recorder.finish()?;
+    eprintln!(
+        "{} samples, {} dropped, {} corrupted",
+        stats.samples, stats.dropped, stats.corrupted
+    );
+    Ok(())
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    if let Err(e) = run(&options) {
+        eprintln!("{}: {}", options.path.display(), e);
+        process::exit(1);
+    }
+}
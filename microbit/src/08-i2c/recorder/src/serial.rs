@@ -0,0 +1,73 @@
+//! Opens the board's serial port, or anything else that can be read like
+//! a capture file.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Opens `path` and, if it's a terminal, switches it to raw mode at
+/// `baud`, so that no byte gets translated or swallowed.
+pub fn open(path: &Path, baud: u32) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)
+        .or_else(|_| File::open(path))?;
+    if unsafe { libc::isatty(file.as_raw_fd()) } == 1 {
+        make_raw(&file, baud)?;
+    }
+    Ok(file)
+}
+
+pub fn make_raw(file: &File, baud: u32) -> io::Result<()> {
+    let speed = speed(baud).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported baud rate {}", baud),
+        )
+    })?;
+
+    let fd = file.as_raw_fd();
+    unsafe {
+        let mut termios = MaybeUninit::uninit();
+        check(libc::tcgetattr(fd, termios.as_mut_ptr()))?;
+        let mut termios = termios.assume_init();
+        libc::cfmakeraw(&mut termios);
+        check(libc::cfsetspeed(&mut termios, speed))?;
+        termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+        // Block until at least one byte is there.
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        check(libc::tcsetattr(fd, libc::TCSANOW, &termios))
+    }
+}
+
+/// Whether a read error just means the other side went away, which is
+/// what a terminal reports instead of the end of file.
+pub fn is_hangup(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(libc::EIO)
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn speed(baud: u32) -> Option<libc::speed_t> {
+    Some(match baud {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _ => return None,
+    })
+}
@@ -0,0 +1,175 @@
+//! Runs the recorder against a pseudo terminal that plays the part of the
+//! board, the same way it would talk to /dev/ttyACM0.
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::process::{Command, Stdio};
+use std::ptr;
+
+use recorder::serial;
+use sensor_stream::{Sample, Sensor, FRAME_SIZE};
+
+/// Returns the board's end, the recorder's end and its path. The
+/// recorder's end has to stay open until the recorder opened it itself,
+/// before that reading from the board's end fails.
+fn pty_pair() -> (File, File, String) {
+    let mut board = 0;
+    let mut computer = 0;
+    let mut name = [0 as libc::c_char; 64];
+    let result = unsafe {
+        libc::openpty(
+            &mut board,
+            &mut computer,
+            name.as_mut_ptr(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    assert_eq!(result, 0, "openpty failed");
+    // Otherwise the recorder inherits the board's end and never sees it
+    // hang up.
+    for fd in [board, computer] {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    let computer = unsafe { File::from_raw_fd(computer) };
+    // Raw right away, so nothing gets mangled before the recorder gets to
+    // it.
+    serial::make_raw(&computer, 115200).unwrap();
+
+    let path = unsafe { CStr::from_ptr(name.as_ptr()) };
+    let board = unsafe { File::from_raw_fd(board) };
+    (board, computer, path.to_str().unwrap().to_string())
+}
+
+fn frame(sequence: u16, sensor: Sensor) -> [u8; FRAME_SIZE] {
+    let sample = Sample {
+        sequence,
+        timestamp: sequence as u32 * 2500,
+        sensor,
+        x: sequence as i32,
+        y: -(sequence as i32),
+        z: 1000,
+    };
+    let mut frame = [0; FRAME_SIZE];
+    sample.encode(&mut frame);
+    frame
+}
+
+#[test]
+fn records_from_serial_port() {
+    let (mut board, computer, path) = pty_pair();
+    let mut recorder = Command::new(env!("CARGO_BIN_EXE_recorder"))
+        .args([path.as_str(), "--send", "stream both"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The command arrives first.
+    let mut command = [0; 12];
+    board.read_exact(&mut command).unwrap();
+    assert_eq!(&command, b"stream both\r");
+    drop(computer);
+
+    // What the board sends: the shell's newline, the zero that starts the
+    // stream, frames 0 to 9 with 4 and 5 lost and 7 corrupted.
+    let mut stream = b"\r\n\0".to_vec();
+    for sequence in 0..10 {
+        let sensor = if sequence % 2 == 0 {
+            Sensor::Accelerometer
+        } else {
+            Sensor::Magnetometer
+        };
+        let mut frame = frame(sequence, sensor);
+        match sequence {
+            4 | 5 => continue,
+            7 => frame[FRAME_SIZE - 2] ^= 0x40,
+            _ => {}
+        }
+        stream.extend_from_slice(&frame);
+    }
+    board.write_all(&stream).unwrap();
+
+    let mut csv = BufReader::new(recorder.stdout.take().unwrap());
+    let mut lines = Vec::new();
+    while !lines
+        .last()
+        .is_some_and(|line: &String| line.starts_with("9,"))
+    {
+        let mut line = String::new();
+        assert!(csv.read_line(&mut line).unwrap() > 0, "recorder quit early");
+        lines.push(line.trim_end().to_string());
+    }
+    // Hanging up ends the recording.
+    drop(board);
+    assert!(recorder.wait().unwrap().success());
+
+    assert_eq!(
+        lines,
+        [
+            "sequence,timestamp_us,sensor,x,y,z",
+            "0,0,accelerometer,0,0,1000",
+            "1,2500,magnetometer,1,-1,1000",
+            "2,5000,accelerometer,2,-2,1000",
+            "3,7500,magnetometer,3,-3,1000",
+            "6,15000,accelerometer,6,-6,1000",
+            "8,20000,accelerometer,8,-8,1000",
+            "9,22500,magnetometer,9,-9,1000",
+        ]
+    );
+
+    let mut log = String::new();
+    recorder
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut log)
+        .unwrap();
+    assert_eq!(
+        log,
+        "dropped 2 frame(s) before #6\n\
+         bad frame: CRC mismatch\n\
+         dropped 1 frame(s) before #8\n\
+         7 samples, 3 dropped, 1 corrupted\n"
+    );
+}
+
+#[test]
+fn records_from_file() {
+    let path = std::env::temp_dir().join(format!("recorder-{}.bin", std::process::id()));
+    let mut capture = vec![0];
+    for sequence in 65534..=65535 {
+        capture.extend_from_slice(&frame(sequence, Sensor::Magnetometer));
+    }
+    capture.extend_from_slice(&frame(0, Sensor::Magnetometer));
+    std::fs::write(&path, &capture).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_recorder"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(csv.lines().count(), 4);
+    assert!(csv.ends_with("0,0,magnetometer,0,0,1000\n"));
+    // Wrapping around isn't a gap.
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "3 samples, 0 dropped, 0 corrupted\n"
+    );
+}
+
+#[test]
+fn missing_port() {
+    let output = Command::new(env!("CARGO_BIN_EXE_recorder"))
+        .arg("/dev/does-not-exist")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.starts_with("/dev/does-not-exist: "), "{}", error);
+}
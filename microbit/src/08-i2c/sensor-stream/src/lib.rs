@@ -0,0 +1,252 @@
+//! The binary format the board streams sensor samples in.
+//!
+//! Text like `Acceleration: x 12 y -4 z 1010` is easy to read but takes
+//! three times the bytes and a lost character garbles a whole line. Here
+//! every sample is one frame instead:
+//!
+//! | Bytes | Field                                       |
+//! |-------|---------------------------------------------|
+//! | 2     | sequence number, counting every frame sent  |
+//! | 4     | timestamp in microseconds                   |
+//! | 1     | sensor, 1 = accelerometer, 2 = magnetometer |
+//! | 3 × 4 | x, y and z                                  |
+//! | 2     | CRC-16 of all of the above                  |
+//!
+//! All little endian. The frame is then [COBS] encoded, which gets rid of
+//! every zero byte, and ends with a single zero. A receiver that starts in
+//! the middle of the stream or loses bytes just waits for the next zero,
+//! the CRC catches frames that got corrupted and gaps in the sequence
+//! numbers show the ones that got lost.
+//!
+//! [COBS]: https://en.wikipedia.org/wiki/Consistent_Overhead_Byte_Stuffing
+
+#![no_std]
+
+use core::convert::TryInto;
+use core::fmt;
+
+/// Length of a frame before encoding.
+pub const RAW_SIZE: usize = 2 + 4 + 1 + 3 * 4 + 2;
+
+/// Length of an encoded frame including the final zero. COBS adds one
+/// byte for every 254.
+pub const FRAME_SIZE: usize = RAW_SIZE + 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sensor {
+    Accelerometer = 1,
+    Magnetometer = 2,
+}
+
+impl Sensor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sensor::Accelerometer => "accelerometer",
+            Sensor::Magnetometer => "magnetometer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub sequence: u16,
+    /// Microseconds, wrapping around about every 71 minutes.
+    pub timestamp: u32,
+    pub sensor: Sensor,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Not valid COBS.
+    Encoding,
+    /// Too long or too short for a sample.
+    Length,
+    /// The CRC doesn't match, the frame got corrupted on the way.
+    Crc,
+    UnknownSensor(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Encoding => f.write_str("invalid COBS encoding"),
+            Error::Length => f.write_str("wrong frame length"),
+            Error::Crc => f.write_str("CRC mismatch"),
+            Error::UnknownSensor(id) => write!(f, "unknown sensor {}", id),
+        }
+    }
+}
+
+impl Sample {
+    /// Encodes the sample into `frame`, ready to be sent as is.
+    pub fn encode(&self, frame: &mut [u8; FRAME_SIZE]) {
+        let mut raw = [0; RAW_SIZE];
+        raw[0..2].copy_from_slice(&self.sequence.to_le_bytes());
+        raw[2..6].copy_from_slice(&self.timestamp.to_le_bytes());
+        raw[6] = self.sensor as u8;
+        raw[7..11].copy_from_slice(&self.x.to_le_bytes());
+        raw[11..15].copy_from_slice(&self.y.to_le_bytes());
+        raw[15..19].copy_from_slice(&self.z.to_le_bytes());
+        let crc = crc16(&raw[..RAW_SIZE - 2]);
+        raw[RAW_SIZE - 2..].copy_from_slice(&crc.to_le_bytes());
+
+        let len = cobs_encode(&raw, frame);
+        debug_assert_eq!(len, FRAME_SIZE - 1);
+        frame[FRAME_SIZE - 1] = 0;
+    }
+
+    /// Decodes a frame without its final zero.
+    pub fn decode(encoded: &[u8]) -> Result<Sample, Error> {
+        let mut raw = [0; FRAME_SIZE];
+        let len = cobs_decode(encoded, &mut raw)?;
+        if len != RAW_SIZE {
+            return Err(Error::Length);
+        }
+        let (data, crc) = raw[..RAW_SIZE].split_at(RAW_SIZE - 2);
+        if crc16(data) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err(Error::Crc);
+        }
+
+        let i32_at = |i: usize| i32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        let sensor = match data[6] {
+            1 => Sensor::Accelerometer,
+            2 => Sensor::Magnetometer,
+            id => return Err(Error::UnknownSensor(id)),
+        };
+        Ok(Sample {
+            sequence: u16::from_le_bytes([data[0], data[1]]),
+            timestamp: u32::from_le_bytes(data[2..6].try_into().unwrap()),
+            sensor,
+            x: i32_at(7),
+            y: i32_at(11),
+            z: i32_at(15),
+        })
+    }
+}
+
+/// Splits a byte stream into samples.
+///
+/// Everything up to the first zero is skipped, since it's probably the
+/// tail of a frame or the echo of the command that started the stream.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    frame: [u8; FRAME_SIZE],
+    len: usize,
+    /// Seen the first zero.
+    synced: bool,
+    /// The current frame didn't fit into `frame`.
+    overflow: bool,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub const fn new() -> Decoder {
+        Decoder {
+            frame: [0; FRAME_SIZE],
+            len: 0,
+            synced: false,
+            overflow: false,
+        }
+    }
+
+    /// Takes the next byte, and returns the sample (or why there isn't one)
+    /// once a frame is complete.
+    pub fn push(&mut self, b: u8) -> Option<Result<Sample, Error>> {
+        if b != 0 {
+            if self.len < self.frame.len() {
+                self.frame[self.len] = b;
+                self.len += 1;
+            } else {
+                self.overflow = true;
+            }
+            return None;
+        }
+
+        let len = core::mem::replace(&mut self.len, 0);
+        let overflow = core::mem::replace(&mut self.overflow, false);
+        if !core::mem::replace(&mut self.synced, true) || len == 0 {
+            return None;
+        }
+        if overflow {
+            return Some(Err(Error::Length));
+        }
+        Some(Sample::decode(&self.frame[..len]))
+    }
+}
+
+/// CRC-16/CCITT-FALSE, polynomial 0x1021 starting from 0xFFFF.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffff_u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// COBS encodes `data` into `out`, which has to be at least one byte
+/// longer per 254 bytes of data, and returns the encoded length. Doesn't
+/// add the final zero.
+pub fn cobs_encode(data: &[u8], out: &mut [u8]) -> usize {
+    // Where the length of the current block goes
+    let mut code_at = 0;
+    let mut code = 1;
+    let mut len = 1;
+    for &b in data {
+        if b != 0 {
+            out[len] = b;
+            len += 1;
+            code += 1;
+        }
+        if b == 0 || code == 0xff {
+            out[code_at] = code;
+            code_at = len;
+            code = 1;
+            len += 1;
+        }
+    }
+    out[code_at] = code;
+    len
+}
+
+/// Reverses [`cobs_encode`], `encoded` not including the final zero.
+/// Returns the decoded length.
+pub fn cobs_decode(encoded: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    let mut i = 0;
+    let mut len = 0;
+    while i < encoded.len() {
+        let code = encoded[i] as usize;
+        if code == 0 || i + code > encoded.len() {
+            return Err(Error::Encoding);
+        }
+        let block = &encoded[i + 1..i + code];
+        if block.contains(&0) {
+            return Err(Error::Encoding);
+        }
+        out.get_mut(len..len + block.len())
+            .ok_or(Error::Length)?
+            .copy_from_slice(block);
+        len += block.len();
+        i += code;
+        // A full block isn't followed by a zero, and neither is the end.
+        if code != 0xff && i < encoded.len() {
+            *out.get_mut(len).ok_or(Error::Length)? = 0;
+            len += 1;
+        }
+    }
+    Ok(len)
+}
@@ -0,0 +1,151 @@
+use sensor_stream::{cobs_decode, cobs_encode, crc16, Decoder, Error, Sample, Sensor, FRAME_SIZE};
+
+fn sample(sequence: u16) -> Sample {
+    Sample {
+        sequence,
+        timestamp: 0x0100_0000 + sequence as u32 * 2500,
+        sensor: Sensor::Accelerometer,
+        x: -12,
+        y: 0,
+        z: 1010,
+    }
+}
+
+fn encode(sample: &Sample) -> [u8; FRAME_SIZE] {
+    let mut frame = [0; FRAME_SIZE];
+    sample.encode(&mut frame);
+    frame
+}
+
+#[test]
+fn crc_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x29b1);
+    assert_eq!(crc16(b""), 0xffff);
+}
+
+#[test]
+fn cobs_examples() {
+    // From the COBS paper and Wikipedia
+    let cases: &[(&[u8], &[u8])] = &[
+        (&[0x00], &[0x01, 0x01]),
+        (&[0x00, 0x00], &[0x01, 0x01, 0x01]),
+        (&[0x11, 0x22, 0x00, 0x33], &[0x03, 0x11, 0x22, 0x02, 0x33]),
+        (&[0x11, 0x22, 0x33, 0x44], &[0x05, 0x11, 0x22, 0x33, 0x44]),
+        (&[0x11, 0x00, 0x00, 0x00], &[0x02, 0x11, 0x01, 0x01, 0x01]),
+    ];
+    for &(data, encoded) in cases {
+        let mut out = [0; 16];
+        let len = cobs_encode(data, &mut out);
+        assert_eq!(&out[..len], encoded);
+
+        let mut back = [0; 16];
+        let len = cobs_decode(encoded, &mut back).unwrap();
+        assert_eq!(&back[..len], data);
+    }
+}
+
+#[test]
+fn cobs_long_blocks() {
+    let data: Vec<u8> = (1..=255).chain(0..=255).map(|b| b as u8).collect();
+    let mut out = [0; 600];
+    let len = cobs_encode(&data, &mut out);
+    assert!(!out[..len].contains(&0));
+
+    let mut back = [0; 600];
+    let back_len = cobs_decode(&out[..len], &mut back).unwrap();
+    assert_eq!(&back[..back_len], &data[..]);
+}
+
+#[test]
+fn cobs_rejects_garbage() {
+    let mut out = [0; 16];
+    assert_eq!(cobs_decode(&[0x05, 0x11], &mut out), Err(Error::Encoding));
+    assert_eq!(
+        cobs_decode(&[0x03, 0x00, 0x11], &mut out),
+        Err(Error::Encoding)
+    );
+    assert_eq!(
+        cobs_decode(&[0x05, 1, 2, 3, 4], &mut out[..2]),
+        Err(Error::Length)
+    );
+}
+
+#[test]
+fn round_trip() {
+    let samples = [
+        sample(0),
+        sample(65535),
+        Sample {
+            sequence: 7,
+            timestamp: u32::MAX,
+            sensor: Sensor::Magnetometer,
+            x: i32::MIN,
+            y: i32::MAX,
+            z: 0,
+        },
+    ];
+    for sample in &samples {
+        let frame = encode(sample);
+        assert_eq!(frame[FRAME_SIZE - 1], 0);
+        assert!(!frame[..FRAME_SIZE - 1].contains(&0));
+        assert_eq!(Sample::decode(&frame[..FRAME_SIZE - 1]), Ok(*sample));
+    }
+}
+
+#[test]
+fn corruption_is_caught() {
+    let frame = encode(&sample(3));
+    for i in 1..FRAME_SIZE - 1 {
+        let mut corrupted = frame;
+        corrupted[i] ^= 0x04;
+        if corrupted[i] == 0 {
+            continue;
+        }
+        assert!(
+            Sample::decode(&corrupted[..FRAME_SIZE - 1]).is_err(),
+            "flipped byte {}",
+            i
+        );
+    }
+}
+
+#[test]
+fn decoder_splits_stream() {
+    let mut stream = b"stream accel\r\n".to_vec();
+    for sequence in 10..13 {
+        stream.extend_from_slice(&encode(&sample(sequence)));
+    }
+
+    let mut decoder = Decoder::new();
+    let decoded: Vec<_> = stream.iter().filter_map(|&b| decoder.push(b)).collect();
+    // The text and the first frame share the part before the first zero.
+    assert_eq!(decoded, [Ok(sample(11)), Ok(sample(12))]);
+}
+
+#[test]
+fn decoder_starts_after_zero() {
+    let mut stream = vec![0];
+    stream.extend_from_slice(&encode(&sample(1)));
+    let mut decoder = Decoder::new();
+    let decoded: Vec<_> = stream.iter().filter_map(|&b| decoder.push(b)).collect();
+    assert_eq!(decoded, [Ok(sample(1))]);
+}
+
+#[test]
+fn decoder_recovers_from_lost_bytes() {
+    let mut stream = vec![0];
+    let mut broken = encode(&sample(1)).to_vec();
+    broken.remove(5);
+    stream.extend_from_slice(&broken);
+    // Lost delimiter, two frames run together
+    stream.extend_from_slice(&encode(&sample(2))[..FRAME_SIZE - 1]);
+    stream.extend_from_slice(&encode(&sample(3)));
+    stream.extend_from_slice(&encode(&sample(4)));
+
+    let mut decoder = Decoder::new();
+    let decoded: Vec<_> = stream.iter().filter_map(|&b| decoder.push(b)).collect();
+    assert_eq!(decoded.len(), 3);
+    assert!(decoded[0].is_err());
+    assert_eq!(decoded[1], Err(Error::Length));
+    assert_eq!(decoded[2], Ok(sample(4)));
+}
@@ -0,0 +1,172 @@
+//! Pretends to be a micro:bit running the I2C chapter, behind a pseudo
+//! terminal. It prints the terminal's path and then answers on it with
+//! the same line editor and shell as the firmware, just with made up
+//! sensor readings:
+//!
+//! ```text
+//! $ cargo run --bin fake-device
+//! /dev/pts/7
+//! $ serial-console /dev/pts/7 accelerometer
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::serial;
+use line_editor::LineEditor;
+use recorder::serial::{is_hangup, make_raw};
+use shell::{reading, Args, Command, Shell};
+
+/// The board's end of the pseudo terminal.
+struct Pty(File);
+
+impl serial::Read<u8> for Pty {
+    type Error = io::Error;
+
+    fn read(&mut self) -> nb::Result<u8, io::Error> {
+        let mut b = [0];
+        match self.0.read(&mut b) {
+            Ok(1) => Ok(b[0]),
+            Ok(_) => Err(nb::Error::WouldBlock),
+            // Nobody has the other end open, like a board without a
+            // terminal attached. Wait for one.
+            Err(e) if is_hangup(&e) => {
+                thread::sleep(Duration::from_millis(10));
+                Err(nb::Error::WouldBlock)
+            }
+            Err(e) => Err(nb::Error::Other(e)),
+        }
+    }
+}
+
+impl serial::Write<u8> for Pty {
+    type Error = io::Error;
+
+    fn write(&mut self, b: u8) -> nb::Result<(), io::Error> {
+        self.0.write_all(&[b]).map_err(nb::Error::Other)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+impl fmt::Write for Pty {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Readings go up by one every time, so that tests can tell them apart.
+#[derive(Default)]
+struct Sensor {
+    readings: i32,
+}
+
+impl Sensor {
+    fn next(&mut self) -> (i32, i32, i32) {
+        self.readings += 1;
+        (self.readings, -self.readings, 1000)
+    }
+}
+
+// Same commands and output as in the firmware.
+static SHELL: Shell<Sensor> = Shell::new(&[
+    Command {
+        name: "accelerometer",
+        args: "[count]",
+        help: "print acceleration readings, 2 by default",
+        run: accelerometer,
+    },
+    Command {
+        name: "magnetometer",
+        args: "[count]",
+        help: "print magnetic field readings, 2 by default",
+        run: magnetometer,
+    },
+]);
+
+fn accelerometer<'a>(
+    sensor: &mut Sensor,
+    args: &mut Args<'a>,
+    out: &mut dyn fmt::Write,
+) -> shell::Result<'a> {
+    let count: u32 = if args.is_empty() {
+        2
+    } else {
+        args.int("count")?
+    };
+    for _ in 0..count {
+        let (x, y, z) = sensor.next();
+        reading::write(out, reading::ACCELERATION, x, y, z)?;
+    }
+    Ok(())
+}
+
+fn magnetometer<'a>(
+    sensor: &mut Sensor,
+    args: &mut Args<'a>,
+    out: &mut dyn fmt::Write,
+) -> shell::Result<'a> {
+    let count: u32 = if args.is_empty() {
+        2
+    } else {
+        args.int("count")?
+    };
+    for _ in 0..count {
+        let (x, y, z) = sensor.next();
+        reading::write(out, reading::MAGNETIZATION, x, y, z)?;
+    }
+    Ok(())
+}
+
+/// Returns the board's end and the path of the other one.
+fn open_pty() -> io::Result<(File, String)> {
+    let mut board = 0;
+    let mut computer = 0;
+    let mut name = [0 as libc::c_char; 64];
+    let result = unsafe {
+        libc::openpty(
+            &mut board,
+            &mut computer,
+            name.as_mut_ptr(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let board = unsafe { File::from_raw_fd(board) };
+    let computer = unsafe { File::from_raw_fd(computer) };
+    // So nothing gets translated before the console opens it.
+    make_raw(&computer, 115200)?;
+
+    let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+    Ok((board, name.to_string_lossy().into_owned()))
+}
+
+fn main() -> io::Result<()> {
+    let (board, path) = open_pty()?;
+    println!("{}", path);
+    io::stdout().flush()?;
+
+    let mut serial = Pty(board);
+    let mut sensor = Sensor::default();
+    let mut editor = LineEditor::<32, 8>::new();
+    loop {
+        // Like the firmware's read_line.
+        fmt::Write::write_str(&mut serial, "> ").ok();
+        let line = match editor.read_line(&mut serial) {
+            Ok(line) => line,
+            Err(e) if is_hangup(&e) => continue,
+            Err(e) => return Err(e),
+        };
+        SHELL.run_line(&line, &mut sensor, &mut serial).ok();
+    }
+}
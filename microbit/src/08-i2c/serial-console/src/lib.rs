@@ -0,0 +1,124 @@
+//! Talks to the board's serial console like a person at minicom would:
+//! waits for the `> ` prompt, types a command, and collects everything
+//! up to the next prompt.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub mod reply;
+
+pub use reply::{Line, Reply};
+
+const PROMPT: &[u8] = b"\r\n> ";
+
+/// How long the board has to be silent before we take it as done.
+const QUIET: Duration = Duration::from_millis(50);
+
+pub struct Console {
+    port: File,
+    /// How long to wait for the board to answer.
+    timeout: Duration,
+    /// Bytes after the last prompt.
+    pending: Vec<u8>,
+}
+
+impl Console {
+    /// Opens the serial port and waits for a prompt, so that whatever was
+    /// sitting in the buffers doesn't end up in the first reply.
+    pub fn connect(path: &Path, baud: u32, timeout: Duration) -> io::Result<Console> {
+        let port = recorder::serial::open(path, baud)?;
+        let mut console = Console {
+            port,
+            timeout,
+            pending: Vec::new(),
+        };
+        // An empty line just gets a new prompt.
+        console.port.write_all(b"\r")?;
+        console.read_until_prompt()?;
+        // There might have been more than one prompt waiting.
+        while wait_readable(&console.port, QUIET)? {
+            let mut buffer = [0; 256];
+            if console.port.read(&mut buffer)? == 0 {
+                break;
+            }
+        }
+        console.pending.clear();
+        Ok(console)
+    }
+
+    /// Runs `command` and returns what it printed, parsed.
+    pub fn run(&mut self, command: &str) -> io::Result<Reply> {
+        let command = command.trim();
+        if command.contains(|c: char| c.is_control()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "commands can't contain control characters",
+            ));
+        }
+        write!(self.port, "{}\r", command)?;
+        let output = self.read_until_prompt()?;
+        let output = String::from_utf8_lossy(&output);
+
+        // The first line is the echo of what we typed.
+        let mut lines = output.split("\r\n");
+        let echo = lines.next().unwrap_or("");
+        if echo != command {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected the echo of {:?}, got {:?}", command, echo),
+            ));
+        }
+        Ok(Reply::parse(command, lines))
+    }
+
+    /// Returns everything before the next prompt.
+    fn read_until_prompt(&mut self) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(end) = find(&self.pending, PROMPT) {
+                let rest = self.pending.split_off(end + PROMPT.len());
+                let mut output = std::mem::replace(&mut self.pending, rest);
+                output.truncate(end);
+                return Ok(output);
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::ZERO || !wait_readable(&self.port, left)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no prompt from the board",
+                ));
+            }
+            let mut buffer = [0; 256];
+            let n = self.port.read(&mut buffer)?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.pending.extend_from_slice(&buffer[..n]);
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Waits up to `timeout` for something to read.
+fn wait_readable(file: &File, timeout: Duration) -> io::Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+    match unsafe { libc::poll(&mut fd, 1, millis) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
@@ -0,0 +1,124 @@
+//! Runs commands on the board's serial console and prints the replies as
+//! JSON, one object per line.
+//!
+//! ```text
+//! serial-console /dev/ttyACM0 "accelerometer 5" magnetometer
+//! serial-console /dev/ttyACM0 < commands.txt
+//! ```
+//!
+//! Without commands on the command line they're read from stdin, one per
+//! line, skipping empty ones and `#` comments. Exits with 1 if any of them
+//! failed.
+
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use serial_console::{Console, Line, Reply};
+
+const USAGE: &str =
+    "usage: serial-console <serial port> [--baud <rate>] [--timeout <ms>] [--raw] [command ...]";
+
+struct Options {
+    path: PathBuf,
+    baud: u32,
+    timeout: Duration,
+    /// Print the output as the board sent it instead of JSON.
+    raw: bool,
+    commands: Vec<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut baud = 115200;
+    let mut timeout = Duration::from_secs(2);
+    let mut raw = false;
+    let mut commands = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--baud" => {
+                let value = args.next().ok_or("--baud needs a rate")?;
+                baud = value
+                    .parse()
+                    .map_err(|_| format!("invalid baud rate {:?}", value))?;
+            }
+            "--timeout" => {
+                let value = args.next().ok_or("--timeout needs milliseconds")?;
+                let millis = value
+                    .parse()
+                    .map_err(|_| format!("invalid timeout {:?}", value))?;
+                timeout = Duration::from_millis(millis);
+            }
+            "--raw" => raw = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => {
+                return Err(format!("unknown option {:?}\n{}", arg, USAGE))
+            }
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => commands.push(arg),
+        }
+    }
+    Ok(Options {
+        path: path.ok_or(USAGE)?,
+        baud,
+        timeout,
+        raw,
+        commands,
+    })
+}
+
+fn print(reply: &Reply, raw: bool) {
+    if !raw {
+        println!("{}", reply.to_json());
+        return;
+    }
+    for line in &reply.lines {
+        match line {
+            Line::Reading { sensor, x, y, z } => println!("{} {} {} {}", sensor, x, y, z),
+            Line::Error(message) => println!("error: {}", message),
+            Line::Text(text) => println!("{}", text),
+        }
+    }
+}
+
+/// Returns whether all commands succeeded.
+fn run(options: &Options) -> io::Result<bool> {
+    let mut console = Console::connect(&options.path, options.baud, options.timeout)?;
+
+    let stdin = io::stdin();
+    let commands: Box<dyn Iterator<Item = io::Result<String>>> = if options.commands.is_empty() {
+        Box::new(stdin.lock().lines())
+    } else {
+        Box::new(options.commands.iter().cloned().map(Ok))
+    };
+
+    let mut ok = true;
+    for command in commands {
+        let command = command?;
+        let command = command.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+        let reply = console.run(command)?;
+        ok &= !reply.is_error();
+        print(&reply, options.raw);
+    }
+    Ok(ok)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}: {}", options.path.display(), e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Makes sense of what the firmware prints.
+
+use std::fmt::Write;
+
+use shell::reading::{ACCELERATION, MAGNETIZATION};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    /// `Acceleration: x 12 y -4 z 1010` and the magnetometer's version.
+    Reading {
+        sensor: &'static str,
+        x: i32,
+        y: i32,
+        z: i32,
+    },
+    /// `error: ...` from the shell.
+    Error(String),
+    /// Anything else, like `help`.
+    Text(String),
+}
+
+impl Line {
+    pub fn parse(line: &str) -> Line {
+        if let Some(message) = line.strip_prefix("error: ") {
+            return Line::Error(message.to_string());
+        }
+        parse_reading(line).unwrap_or_else(|| Line::Text(line.to_string()))
+    }
+}
+
+/// `<label>: x <x> y <y> z <z>`, as written by `shell::reading::write`.
+fn parse_reading(line: &str) -> Option<Line> {
+    let (label, values) = line.split_once(": ")?;
+    let sensor = match label {
+        ACCELERATION => "accelerometer",
+        MAGNETIZATION => "magnetometer",
+        _ => return None,
+    };
+    let words: Vec<&str> = values.split(' ').collect();
+    match words.as_slice() {
+        ["x", x, "y", y, "z", z] => Some(Line::Reading {
+            sensor,
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+            z: z.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub command: String,
+    pub lines: Vec<Line>,
+}
+
+impl Reply {
+    pub fn parse<'a>(command: &str, output: impl IntoIterator<Item = &'a str>) -> Reply {
+        Reply {
+            command: command.to_string(),
+            lines: output.into_iter().map(Line::parse).collect(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.lines.iter().any(|line| matches!(line, Line::Error(_)))
+    }
+
+    /// One JSON object, readings and errors in their own arrays.
+    pub fn to_json(&self) -> String {
+        let mut readings = Vec::new();
+        let mut errors = Vec::new();
+        let mut text = Vec::new();
+        for line in &self.lines {
+            match line {
+                Line::Reading { sensor, x, y, z } => readings.push(format!(
+                    r#"{{"sensor":"{}","x":{},"y":{},"z":{}}}"#,
+                    sensor, x, y, z
+                )),
+                Line::Error(message) => errors.push(json_string(message)),
+                Line::Text(line) => text.push(json_string(line)),
+            }
+        }
+        format!(
+            r#"{{"command":{},"ok":{},"readings":[{}],"errors":[{}],"text":[{}]}}"#,
+            json_string(&self.command),
+            !self.is_error(),
+            readings.join(","),
+            errors.join(","),
+            text.join(",")
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,143 @@
+//! Runs the console against the fake device, which answers like the
+//! firmware does.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::Duration;
+
+use serial_console::{Console, Line};
+
+struct FakeDevice {
+    process: Child,
+    path: String,
+}
+
+impl FakeDevice {
+    fn start() -> FakeDevice {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_fake-device"))
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut path = String::new();
+        BufReader::new(process.stdout.take().unwrap())
+            .read_line(&mut path)
+            .unwrap();
+        FakeDevice {
+            process,
+            path: path.trim().to_string(),
+        }
+    }
+
+    fn console(&self, args: &[&str], stdin: &str) -> Output {
+        let mut console = Command::new(env!("CARGO_BIN_EXE_serial-console"))
+            .arg(&self.path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        console
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        console.wait_with_output().unwrap()
+    }
+}
+
+impl Drop for FakeDevice {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
+
+fn stdout(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn commands_from_arguments() {
+    let device = FakeDevice::start();
+    let output = device.console(&["accelerometer 1", "magnetometer"], "");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        stdout(&output),
+        [
+            r#"{"command":"accelerometer 1","ok":true,"readings":[{"sensor":"accelerometer","x":1,"y":-1,"z":1000}],"errors":[],"text":[]}"#,
+            r#"{"command":"magnetometer","ok":true,"readings":[{"sensor":"magnetometer","x":2,"y":-2,"z":1000},{"sensor":"magnetometer","x":3,"y":-3,"z":1000}],"errors":[],"text":[]}"#,
+        ]
+    );
+}
+
+#[test]
+fn script_from_stdin() {
+    let device = FakeDevice::start();
+    let script = "# warm up\naccelerometer 1\n\n  magnetometer 1  \n";
+    let output = device.console(&["--raw"], script);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        stdout(&output),
+        ["accelerometer 1 -1 1000", "magnetometer 2 -2 1000"]
+    );
+
+    // The device kept running, a second session picks up where the first
+    // one left off.
+    let output = device.console(&["--raw", "accelerometer 1"], "");
+    assert_eq!(stdout(&output), ["accelerometer 3 -3 1000"]);
+}
+
+#[test]
+fn errors_fail_the_run() {
+    let device = FakeDevice::start();
+    let output = device.console(&["accelerometer x", "blink", "magnetometer 1"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let lines = stdout(&output);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        r#"{"command":"accelerometer x","ok":false,"readings":[],"errors":["count: not a number: \"x\""],"text":["usage: accelerometer [count]"]}"#
+    );
+    assert!(lines[1].contains(r#""errors":["unknown command \"blink\", try help"]"#));
+    assert!(lines[2].contains(r#""ok":true"#));
+}
+
+#[test]
+fn library() {
+    let device = FakeDevice::start();
+    let mut console =
+        Console::connect(device.path.as_ref(), 115200, Duration::from_secs(2)).unwrap();
+
+    let reply = console.run("help").unwrap();
+    assert!(!reply.is_error());
+    assert_eq!(reply.lines[0], Line::Text("commands:".into()));
+    assert_eq!(reply.lines.len(), 4);
+
+    let reply = console.run("accelerometer 3").unwrap();
+    assert_eq!(reply.lines.len(), 3);
+    assert_eq!(
+        reply.lines[2],
+        Line::Reading {
+            sensor: "accelerometer",
+            x: 3,
+            y: -3,
+            z: 1000
+        }
+    );
+}
+
+#[test]
+fn silent_device_times_out() {
+    let device = FakeDevice::start();
+    // Stopped, it doesn't answer any more but the terminal stays.
+    unsafe { libc::kill(device.process.id() as i32, libc::SIGSTOP) };
+    let output = device.console(&["--timeout", "200", "help"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let error = String::from_utf8_lossy(&output.stderr);
+    assert!(error.ends_with("no prompt from the board\n"), "{}", error);
+}
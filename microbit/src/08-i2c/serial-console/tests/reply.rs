@@ -0,0 +1,78 @@
+use serial_console::{Line, Reply};
+use shell::reading;
+
+#[test]
+fn parses_readings() {
+    assert_eq!(
+        Line::parse("Acceleration: x -8 y 20 z 1012"),
+        Line::Reading {
+            sensor: "accelerometer",
+            x: -8,
+            y: 20,
+            z: 1012
+        }
+    );
+    assert_eq!(
+        Line::parse("Magnetization(?): x 1 y 2 z 3"),
+        Line::Reading {
+            sensor: "magnetometer",
+            x: 1,
+            y: 2,
+            z: 3
+        }
+    );
+}
+
+#[test]
+fn parses_what_the_firmware_prints() {
+    let mut output = String::new();
+    reading::write(&mut output, reading::ACCELERATION, -8, 20, 1012).unwrap();
+    reading::write(&mut output, reading::MAGNETIZATION, 1, 2, 3).unwrap();
+    // `Console::run` splits on the line endings and the prompt comes after
+    // the last one.
+    let output = output.strip_suffix("\r\n").unwrap();
+    let reply = Reply::parse("accelerometer 1", output.split("\r\n"));
+    assert_eq!(
+        reply.lines,
+        [
+            Line::Reading {
+                sensor: "accelerometer",
+                x: -8,
+                y: 20,
+                z: 1012
+            },
+            Line::Reading {
+                sensor: "magnetometer",
+                x: 1,
+                y: 2,
+                z: 3
+            },
+        ]
+    );
+}
+
+#[test]
+fn everything_else_is_text() {
+    for line in [
+        "",
+        "commands:",
+        "Acceleration: x -8 y 20",
+        "Acceleration: x -8 y 20 z big",
+        "Velocity: x 1 y 2 z 3",
+    ] {
+        assert_eq!(Line::parse(line), Line::Text(line.into()));
+    }
+    assert_eq!(
+        Line::parse("error: missing count"),
+        Line::Error("missing count".into())
+    );
+}
+
+#[test]
+fn json_escapes_strings() {
+    let reply = Reply::parse("say \"hi\"", ["tab\there", "back\\slash", "bell\x07"]);
+    assert_eq!(
+        reply.to_json(),
+        r#"{"command":"say \"hi\"","ok":true,"readings":[],"errors":[],"text":["tab\there","back\\slash","bell\u0007"]}"#
+    );
+}
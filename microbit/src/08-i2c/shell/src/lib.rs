@@ -0,0 +1,321 @@
+//! A tiny command shell for the serial console.
+//!
+//! Every chapter lists its commands in a static table and hands each line
+//! it reads to [`Shell::run_line`], which finds the command, lets it parse
+//! its arguments and prints a readable error if anything is off:
+//!
+//! ```text
+//! > accelerometer 3
+//! > magnetometer x
+//! error: count: not a number: "x"
+//! usage: magnetometer [count]
+//! > help
+//! ```
+//!
+//! Commands get the chapter's state as `C`, e.g. the sensor driver, and
+//! write their output to any [`core::fmt::Write`].
+
+#![no_std]
+
+use core::convert::TryFrom;
+use core::fmt::{self, Write};
+use core::str::{self, FromStr, SplitWhitespace};
+
+pub mod reading;
+
+/// What a command returns, `'a` being the lifetime of the line.
+pub type Result<'a> = core::result::Result<(), Error<'a>>;
+
+/// Runs a command with the chapter's state, its arguments and the output.
+pub type Handler<C> = for<'a> fn(&mut C, &mut Args<'a>, &mut dyn Write) -> Result<'a>;
+
+pub struct Command<C: 'static> {
+    pub name: &'static str,
+    /// Arguments as shown by `help`, e.g. `<address> [count]`.
+    pub args: &'static str,
+    /// One line description.
+    pub help: &'static str,
+    pub run: Handler<C>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<'a> {
+    UnknownCommand(&'a str),
+    /// The argument with this name wasn't given.
+    MissingArgument(&'static str),
+    /// The first argument nobody asked for.
+    TooManyArguments(&'a str),
+    InvalidNumber {
+        name: &'static str,
+        value: &'a str,
+    },
+    OutOfRange {
+        name: &'static str,
+        value: &'a str,
+    },
+    /// Not one of the choices listed in the usage.
+    InvalidChoice {
+        name: &'static str,
+        value: &'a str,
+    },
+    /// The command ran but didn't succeed.
+    Failed(&'static str),
+    /// Writing the output failed.
+    Output,
+}
+
+impl Error<'_> {
+    /// Whether the usage of the command would help.
+    fn is_usage(&self) -> bool {
+        matches!(
+            self,
+            Error::MissingArgument(_)
+                | Error::TooManyArguments(_)
+                | Error::InvalidNumber { .. }
+                | Error::OutOfRange { .. }
+                | Error::InvalidChoice { .. }
+        )
+    }
+}
+
+impl From<fmt::Error> for Error<'_> {
+    fn from(_: fmt::Error) -> Self {
+        Error::Output
+    }
+}
+
+impl fmt::Display for Error<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCommand(name) => write!(f, "unknown command {:?}, try help", name),
+            Error::MissingArgument(name) => write!(f, "missing {}", name),
+            Error::TooManyArguments(value) => write!(f, "unexpected argument {:?}", value),
+            Error::InvalidNumber { name, value } => {
+                write!(f, "{}: not a number: {:?}", name, value)
+            }
+            Error::OutOfRange { name, value } => write!(f, "{}: out of range: {}", name, value),
+            Error::InvalidChoice { name, value } => write!(f, "{}: invalid: {:?}", name, value),
+            Error::Failed(reason) => f.write_str(reason),
+            Error::Output => f.write_str("output failed"),
+        }
+    }
+}
+
+/// The arguments after the command name, taken one at a time.
+///
+/// `name` is what the argument is called in error messages.
+pub struct Args<'a> {
+    words: SplitWhitespace<'a>,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a str) -> Args<'a> {
+        Args {
+            words: args.split_whitespace(),
+        }
+    }
+
+    /// Whether all arguments have been taken.
+    pub fn is_empty(&self) -> bool {
+        self.words.clone().next().is_none()
+    }
+
+    /// The next argument as it is.
+    pub fn word(&mut self, name: &'static str) -> core::result::Result<&'a str, Error<'a>> {
+        self.words.next().ok_or(Error::MissingArgument(name))
+    }
+
+    /// An integer, decimal unless it starts with `0x` or `0b`.
+    pub fn int<T: TryFrom<i64>>(
+        &mut self,
+        name: &'static str,
+    ) -> core::result::Result<T, Error<'a>> {
+        let value = self.word(name)?;
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value),
+        };
+        let (radix, digits) = if let Some(digits) = strip_prefix(digits, "0x") {
+            (16, digits)
+        } else if let Some(digits) = strip_prefix(digits, "0b") {
+            (2, digits)
+        } else {
+            (10, digits)
+        };
+        let magnitude = parse_digits(digits, radix, name, value)?;
+        let number = i64::try_from(magnitude).map_err(|_| Error::OutOfRange { name, value })?;
+        let number = if negative { -number } else { number };
+        T::try_from(number).map_err(|_| Error::OutOfRange { name, value })
+    }
+
+    /// An integer in hex, with or without `0x`, e.g. an I2C address.
+    pub fn hex<T: TryFrom<u64>>(
+        &mut self,
+        name: &'static str,
+    ) -> core::result::Result<T, Error<'a>> {
+        let value = self.word(name)?;
+        let digits = strip_prefix(value, "0x").unwrap_or(value);
+        let number = parse_digits(digits, 16, name, value)?;
+        T::try_from(number).map_err(|_| Error::OutOfRange { name, value })
+    }
+
+    /// Anything with a `FromStr`, e.g. a float.
+    pub fn parse<T: FromStr>(&mut self, name: &'static str) -> core::result::Result<T, Error<'a>> {
+        let value = self.word(name)?;
+        value
+            .parse()
+            .map_err(|_| Error::InvalidNumber { name, value })
+    }
+
+    /// One of the named `choices`, e.g. `[("on", true), ("off", false)]`.
+    pub fn choice<T: Copy>(
+        &mut self,
+        name: &'static str,
+        choices: &[(&str, T)],
+    ) -> core::result::Result<T, Error<'a>> {
+        let value = self.word(name)?;
+        choices
+            .iter()
+            .find(|(choice, _)| *choice == value)
+            .map(|&(_, choice)| choice)
+            .ok_or(Error::InvalidChoice { name, value })
+    }
+
+    /// Checks that nothing is left over.
+    pub fn finish(&mut self) -> Result<'a> {
+        match self.words.next() {
+            Some(extra) => Err(Error::TooManyArguments(extra)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Case insensitive, so `0X1E` works too.
+fn strip_prefix<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&value[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+fn parse_digits<'a>(
+    digits: &str,
+    radix: u32,
+    name: &'static str,
+    value: &'a str,
+) -> core::result::Result<u64, Error<'a>> {
+    // from_str_radix would take a sign as well.
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(Error::InvalidNumber { name, value });
+    }
+    u64::from_str_radix(digits, radix).map_err(|_| Error::OutOfRange { name, value })
+}
+
+pub struct Shell<C: 'static> {
+    commands: &'static [Command<C>],
+}
+
+impl<C> Shell<C> {
+    pub const fn new(commands: &'static [Command<C>]) -> Shell<C> {
+        Shell { commands }
+    }
+
+    /// Like [`Shell::run`], for lines straight from the serial port.
+    pub fn run_line(&self, line: &[u8], context: &mut C, out: &mut dyn Write) -> fmt::Result {
+        match str::from_utf8(line) {
+            Ok(line) => self.run(line, context, out),
+            Err(_) => out.write_str("error: not UTF-8\r\n"),
+        }
+    }
+
+    /// Runs the command on `line` and prints what went wrong, if anything.
+    /// Only fails if `out` does.
+    pub fn run(&self, line: &str, context: &mut C, out: &mut dyn Write) -> fmt::Result {
+        let line = line.trim();
+        let (name, args) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], &line[i..]),
+            None => (line, ""),
+        };
+        let mut args = Args::new(args);
+
+        if name.is_empty() {
+            return Ok(());
+        }
+        if name == "help" {
+            return self.help(&mut args, out);
+        }
+
+        let command = match self.commands.iter().find(|c| c.name == name) {
+            Some(command) => command,
+            None => return write!(out, "error: {}\r\n", Error::UnknownCommand(name)),
+        };
+        match (command.run)(context, &mut args, out).and_then(|_| args.finish()) {
+            Ok(()) => Ok(()),
+            Err(Error::Output) => Err(fmt::Error),
+            Err(e) => {
+                write!(out, "error: {}\r\n", e)?;
+                if e.is_usage() {
+                    write!(out, "usage: ")?;
+                    usage(command, 0, out)?;
+                    out.write_str("\r\n")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// `help` lists all commands, `help <command>` shows just that one.
+    fn help(&self, args: &mut Args<'_>, out: &mut dyn Write) -> fmt::Result {
+        let width = self
+            .commands
+            .iter()
+            .map(|c| usage_len(c))
+            .max()
+            .unwrap_or(0)
+            .max("help [command]".len());
+
+        let name = args.words.next();
+        if let Some(extra) = args.words.next() {
+            return write!(out, "error: {}\r\n", Error::TooManyArguments(extra));
+        }
+        match name {
+            None => {
+                out.write_str("commands:\r\n")?;
+                for command in self.commands {
+                    out.write_str("  ")?;
+                    usage(command, width, out)?;
+                    write!(out, "  {}\r\n", command.help)?;
+                }
+                write!(out, "  {:width$}  list the commands\r\n", "help [command]")
+            }
+            Some(name) => match self.commands.iter().find(|c| c.name == name) {
+                Some(command) => {
+                    usage(command, 0, out)?;
+                    write!(out, "\r\n  {}\r\n", command.help)
+                }
+                None => write!(out, "error: {}\r\n", Error::UnknownCommand(name)),
+            },
+        }
+    }
+}
+
+fn usage_len<C>(command: &Command<C>) -> usize {
+    match command.args.len() {
+        0 => command.name.len(),
+        n => command.name.len() + 1 + n,
+    }
+}
+
+/// Writes the command with its arguments, padded to `width`.
+fn usage<C>(command: &Command<C>, width: usize, out: &mut dyn Write) -> fmt::Result {
+    out.write_str(command.name)?;
+    if !command.args.is_empty() {
+        write!(out, " {}", command.args)?;
+    }
+    for _ in usage_len(command)..width {
+        out.write_char(' ')?;
+    }
+    Ok(())
+}
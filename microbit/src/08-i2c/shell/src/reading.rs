@@ -0,0 +1,18 @@
+//! How the `accelerometer` and `magnetometer` commands print a reading:
+//!
+//! ```text
+//! Acceleration: x 12 y -4 z 1010
+//! ```
+//!
+//! The serial console on the computer parses these lines, so the firmware
+//! and the fake device it is tested against both print them through here.
+
+use core::fmt::{self, Write};
+
+pub const ACCELERATION: &str = "Acceleration";
+pub const MAGNETIZATION: &str = "Magnetization(?)";
+
+/// Writes one `<label>: x <x> y <y> z <z>` line.
+pub fn write(out: &mut dyn Write, label: &str, x: i32, y: i32, z: i32) -> fmt::Result {
+    write!(out, "{}: x {} y {} z {}\r\n", label, x, y, z)
+}
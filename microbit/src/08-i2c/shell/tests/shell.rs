@@ -0,0 +1,177 @@
+//! Runs lines through a shell with a few made up commands and checks what
+//! ends up on the console.
+
+use std::fmt::Write;
+
+use shell::{Args, Command, Error, Result, Shell};
+
+#[derive(Debug, Default)]
+struct Device {
+    led: bool,
+    address: u8,
+    offset: i16,
+    gain: f32,
+}
+
+fn led<'a>(device: &mut Device, args: &mut Args<'a>, out: &mut dyn Write) -> Result<'a> {
+    if !args.is_empty() {
+        device.led = args.choice("state", &[("on", true), ("off", false)])?;
+    }
+    write!(out, "led {}\r\n", if device.led { "on" } else { "off" })?;
+    Ok(())
+}
+
+fn set<'a>(device: &mut Device, args: &mut Args<'a>, _: &mut dyn Write) -> Result<'a> {
+    device.address = args.hex("address")?;
+    device.offset = args.int("offset")?;
+    if !args.is_empty() {
+        device.gain = args.parse("gain")?;
+    }
+    Ok(())
+}
+
+fn fail<'a>(_: &mut Device, _: &mut Args<'a>, _: &mut dyn Write) -> Result<'a> {
+    Err(Error::Failed("no answer from the device"))
+}
+
+static SHELL: Shell<Device> = Shell::new(&[
+    Command {
+        name: "led",
+        args: "[on|off]",
+        help: "show or switch the led",
+        run: led,
+    },
+    Command {
+        name: "set",
+        args: "<address> <offset> [gain]",
+        help: "configure the device",
+        run: set,
+    },
+    Command {
+        name: "fail",
+        args: "",
+        help: "always fails",
+        run: fail,
+    },
+]);
+
+fn run(device: &mut Device, line: &str) -> String {
+    let mut out = String::new();
+    SHELL.run(line, device, &mut out).unwrap();
+    out
+}
+
+#[test]
+fn runs_commands() {
+    let mut device = Device::default();
+    assert_eq!(run(&mut device, "led on"), "led on\r\n");
+    assert!(device.led);
+    assert_eq!(run(&mut device, "  led  "), "led on\r\n");
+    assert_eq!(run(&mut device, "led off"), "led off\r\n");
+    assert!(!device.led);
+}
+
+#[test]
+fn empty_line_does_nothing() {
+    assert_eq!(run(&mut Device::default(), ""), "");
+    assert_eq!(run(&mut Device::default(), " \t "), "");
+}
+
+#[test]
+fn parses_arguments() {
+    let mut device = Device::default();
+    assert_eq!(run(&mut device, "set 1e -12 0.5"), "");
+    assert_eq!(
+        (device.address, device.offset, device.gain),
+        (0x1e, -12, 0.5)
+    );
+
+    assert_eq!(run(&mut device, "set 0x19 0x10"), "");
+    assert_eq!((device.address, device.offset), (0x19, 16));
+
+    assert_eq!(run(&mut device, "set 0X7F 0b101"), "");
+    assert_eq!((device.address, device.offset), (0x7f, 5));
+}
+
+#[test]
+fn unknown_command() {
+    assert_eq!(
+        run(&mut Device::default(), "blink 3"),
+        "error: unknown command \"blink\", try help\r\n"
+    );
+}
+
+#[test]
+fn bad_arguments_show_usage() {
+    let mut device = Device::default();
+    let usage = "usage: set <address> <offset> [gain]\r\n";
+    let cases = [
+        ("set", "error: missing address\r\n"),
+        ("set 19", "error: missing offset\r\n"),
+        ("set 1g 0", "error: address: not a number: \"1g\"\r\n"),
+        ("set 100 0", "error: address: out of range: 100\r\n"),
+        ("set 19 40000", "error: offset: out of range: 40000\r\n"),
+        ("set 19 -x", "error: offset: not a number: \"-x\"\r\n"),
+        ("set 19 0x", "error: offset: not a number: \"0x\"\r\n"),
+        ("set 19 +3", "error: offset: not a number: \"+3\"\r\n"),
+        ("set 19 1 fast", "error: gain: not a number: \"fast\"\r\n"),
+        ("set 19 1 2 3", "error: unexpected argument \"3\"\r\n"),
+    ];
+    for (line, error) in cases {
+        assert_eq!(
+            run(&mut device, line),
+            format!("{}{}", error, usage),
+            "{}",
+            line
+        );
+    }
+
+    assert_eq!(
+        run(&mut device, "led dim"),
+        "error: state: invalid: \"dim\"\r\nusage: led [on|off]\r\n"
+    );
+}
+
+#[test]
+fn failures_skip_usage() {
+    assert_eq!(
+        run(&mut Device::default(), "fail"),
+        "error: no answer from the device\r\n"
+    );
+}
+
+#[test]
+fn help_lists_everything() {
+    let expected = [
+        "commands:",
+        "  led [on|off]                   show or switch the led",
+        "  set <address> <offset> [gain]  configure the device",
+        "  fail                           always fails",
+        "  help [command]                 list the commands",
+    ];
+    assert_eq!(
+        run(&mut Device::default(), "help"),
+        expected.map(|line| format!("{}\r\n", line)).concat()
+    );
+}
+
+#[test]
+fn help_for_one_command() {
+    assert_eq!(
+        run(&mut Device::default(), "help set"),
+        "set <address> <offset> [gain]\r\n  configure the device\r\n"
+    );
+    assert_eq!(
+        run(&mut Device::default(), "help nope"),
+        "error: unknown command \"nope\", try help\r\n"
+    );
+}
+
+#[test]
+fn run_line_checks_utf8() {
+    let mut out = String::new();
+    SHELL
+        .run_line(b"led \xff", &mut Device::default(), &mut out)
+        .unwrap();
+    assert_eq!(out, "error: not UTF-8\r\n");
+}
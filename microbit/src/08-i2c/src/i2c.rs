@@ -0,0 +1,232 @@
+//! The `i2c` command: read and write registers of whatever is on the
+//! internal or the external bus, to try out settings without flashing a
+//! new program every time.
+//!
+//! ```text
+//! > i2c read 19 0f
+//! 0f: 33
+//! > i2c ext dump 48
+//! ```
+//!
+//! and the `scan` command, to find out what's there in the first place.
+
+use core::cell::RefCell;
+use core::fmt::Write;
+use embedded_hal::blocking::i2c;
+use heapless::Vec;
+use microbit::hal::twim::{self, Twim};
+use microbit::pac::{TWIM0, TWIM1};
+use shell::{Args, Error};
+
+use crate::State;
+
+/// Most bytes `i2c read` and `i2c write` take at once.
+const MAX_LEN: usize = 32;
+
+/// Lets the sensor driver and the `i2c` command take turns on the same bus.
+pub struct Shared<T: 'static>(&'static RefCell<T>);
+
+impl<T> Shared<T> {
+    pub fn new(bus: &'static RefCell<T>) -> Self {
+        Shared(bus)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared(self.0)
+    }
+}
+
+impl<T: i2c::Write> i2c::Write for Shared<T> {
+    type Error = T::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), T::Error> {
+        self.0.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<T: i2c::Read> i2c::Read for Shared<T> {
+    type Error = T::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), T::Error> {
+        self.0.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<T: i2c::WriteRead> i2c::WriteRead for Shared<T> {
+    type Error = T::Error;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), T::Error> {
+        self.0.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
+
+/// The two buses, the internal one with the sensors and the one on the
+/// edge connector (pins 19 and 20).
+pub struct Buses {
+    pub internal: Shared<Twim<TWIM0>>,
+    pub external: Twim<TWIM1>,
+}
+
+/// Register access on either bus, so the commands don't have to care.
+trait Bus {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), twim::Error>;
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), twim::Error>;
+}
+
+impl<T: i2c::Write<Error = twim::Error> + i2c::WriteRead<Error = twim::Error>> Bus for T {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), twim::Error> {
+        i2c::Write::write(self, address, bytes)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), twim::Error> {
+        i2c::WriteRead::write_read(self, address, bytes, buffer)
+    }
+}
+
+fn bus_error(e: twim::Error) -> Error<'static> {
+    Error::Failed(match e {
+        twim::Error::AddressNack => "no answer from that address",
+        twim::Error::DataNack => "the device refused the data",
+        twim::Error::Overrun => "the bus was too fast for us",
+        _ => "bus error",
+    })
+}
+
+/// `scan [int|ext]`, both buses if none is given.
+pub fn scan<'a>(state: &mut State, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    let (internal, external) = if args.is_empty() {
+        (true, true)
+    } else {
+        args.choice("bus", &[("int", (true, false)), ("ext", (false, true))])?
+    };
+    args.finish()?;
+
+    if internal {
+        let found = common::i2c::scan(&mut state.buses.internal);
+        write!(out, "internal bus:\r\n{}", found)?;
+    }
+    if external {
+        let found = common::i2c::scan(&mut state.buses.external);
+        write!(out, "external bus:\r\n{}", found)?;
+    }
+    Ok(())
+}
+
+pub fn run<'a>(state: &mut State, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    let mut action = args.word("action")?;
+    // The bus is optional and goes first, like the bus number of i2cget.
+    let bus: &mut dyn Bus = match action {
+        "int" => {
+            action = args.word("action")?;
+            &mut state.buses.internal
+        }
+        "ext" => {
+            action = args.word("action")?;
+            &mut state.buses.external
+        }
+        _ => &mut state.buses.internal,
+    };
+    match action {
+        "read" => read(bus, args, out),
+        "write" => write(bus, args),
+        "dump" => dump(bus, args, out),
+        _ => Err(Error::InvalidChoice {
+            name: "action",
+            value: action,
+        }),
+    }
+}
+
+fn address<'a>(args: &mut Args<'a>) -> Result<u8, Error<'a>> {
+    let address: u8 = args.hex("addr")?;
+    if address > 0x7f {
+        return Err(Error::Failed("addresses go up to 7f"));
+    }
+    Ok(address)
+}
+
+/// `read <addr> <reg> [len]`
+fn read<'a>(bus: &mut dyn Bus, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    let address = address(args)?;
+    let register: u8 = args.hex("reg")?;
+    let len: usize = if args.is_empty() { 1 } else { args.int("len")? };
+    args.finish()?;
+    if len == 0 || len > MAX_LEN {
+        return Err(Error::Failed("len goes from 1 to 32"));
+    }
+
+    // Whether the register address goes up after every byte depends on
+    // the device, the accelerometer e.g. wants the top bit of `reg` set.
+    let mut buffer = [0; MAX_LEN];
+    bus.write_read(address, &[register], &mut buffer[..len])
+        .map_err(bus_error)?;
+    for (row, bytes) in buffer[..len].chunks(16).enumerate() {
+        write!(out, "{:02x}:", register.wrapping_add(row as u8 * 16))?;
+        for b in bytes {
+            write!(out, " {:02x}", b)?;
+        }
+        write!(out, "\r\n")?;
+    }
+    Ok(())
+}
+
+/// `write <addr> <reg> <byte>...`
+fn write<'a>(bus: &mut dyn Bus, args: &mut Args<'a>) -> shell::Result<'a> {
+    let address = address(args)?;
+    let register: u8 = args.hex("reg")?;
+    let mut bytes: Vec<u8, { MAX_LEN + 1 }> = Vec::new();
+    // Can't fail, it's empty.
+    let _ = bytes.push(register);
+    loop {
+        let byte: u8 = args.hex("byte")?;
+        bytes
+            .push(byte)
+            .map_err(|_| Error::Failed("at most 32 bytes at once"))?;
+        if args.is_empty() {
+            break;
+        }
+    }
+    bus.write(address, &bytes).map_err(bus_error)
+}
+
+/// `dump <addr>`: all 256 registers, one at a time.
+fn dump<'a>(bus: &mut dyn Bus, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    let address = address(args)?;
+    args.finish()?;
+
+    write!(out, "   ")?;
+    for column in 0..16 {
+        write!(out, "  {:x}", column)?;
+    }
+    write!(out, "\r\n")?;
+    for row in 0..16u8 {
+        write!(out, "{:02x}:", row * 16)?;
+        for column in 0..16 {
+            let mut value = [0];
+            match bus.write_read(address, &[row * 16 + column], &mut value) {
+                Ok(()) => write!(out, " {:02x}", value[0])?,
+                // Stop right away if nobody is there.
+                Err(twim::Error::AddressNack) if row == 0 && column == 0 => {
+                    write!(out, "\r\n")?;
+                    return Err(bus_error(twim::Error::AddressNack));
+                }
+                // Some devices refuse registers they don't have.
+                Err(_) => write!(out, " --")?,
+            }
+        }
+        write!(out, "\r\n")?;
+    }
+    Ok(())
+}
@@ -6,13 +6,20 @@ use core::fmt::{Debug, Write};
 use cortex_m_rt::entry;
 use embedded_hal::serial;
 use heapless::Vec;
-use lsm303agr::Lsm303agr;
+use common::LineEditor;
+use lsm303agr::{interface::I2cInterface, mode::MagContinuous, Lsm303agr};
 use microbit::hal::uarte::{Baudrate, Parity};
+use microbit::hal::gpio::Level;
 use microbit::hal::{prelude::*, uarte};
-use microbit::pac::UARTE0;
+use microbit::pac::{TWIM0, TWIM1, UARTE0};
 use panic_rtt_target as _;
+use shell::{reading, Args, Command, Error, Shell};
 use rtt_target::{rprintln, rtt_init_print};
 use core::str;
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
+use microbit::pac::{self, interrupt};
 
 use microbit::{hal::twim, pac::twim0::frequency::FREQUENCY_A};
 
@@ -25,15 +32,113 @@ const MAGNETOMETER_ID_REG: u8 = 0x4f;
 const ACCELEROMETER_ID: u8 = 0b_0011_0011;
 const MAGNETOMETER_ID: u8 = 0b_0100_0000;
 
-mod serial_setup;
-use serial_setup::UartePort;
+use common::cpu::{Load, Sleeping};
+use common::data_ready::{self, next_accel, next_mag, DataReady, Events, Int1};
+use common::serial::{
+    BufferedUartePort, Buffers, FlowControl, Framing, ParityBit, StopBits, UarteHandler,
+};
+
+mod settings;
+
+mod stream;
+use stream::{Clock, Format, Sensors, Stream};
+
+mod i2c;
+use i2c::{Buses, Shared};
+
+/// Size of the receive and transmit rings.
+const RX_SIZE: usize = 64;
+const TX_SIZE: usize = 256;
+
+type Serial = BufferedUartePort<UARTE0, RX_SIZE, TX_SIZE>;
+
+/// Longest command, and how many of them Up / Down can bring back.
+const LINE_SIZE: usize = 32;
+const HISTORY: usize = 8;
+
+type Editor = LineEditor<LINE_SIZE, HISTORY>;
+
+type Sensor = Lsm303agr<I2cInterface<Shared<twim::Twim<TWIM0>>>, MagContinuous>;
+
+/// What the commands work with.
+struct State {
+    sensor: Sensor,
+    buses: Buses,
+    /// Set by the `stream` command, streaming happens in the main loop
+    /// since it needs the serial port for itself.
+    stream: Option<Stream>,
+    /// Set by the `baud` command, for the same reason.
+    framing: Option<Framing>,
+    load: Load,
+}
+
+static SHELL: Shell<State> = Shell::new(&[
+    Command {
+        name: "accelerometer",
+        args: "[count]",
+        help: "print acceleration readings, 2 by default",
+        run: accelerometer,
+    },
+    Command {
+        name: "magnetometer",
+        args: "[count]",
+        help: "print magnetic field readings, 2 by default",
+        run: magnetometer,
+    },
+    Command {
+        name: "stream",
+        args: "<accel|mag|both> <hz> [csv|json|bin]",
+        help: "send samples until a key is pressed, CSV by default",
+        run: stream,
+    },
+    Command {
+        name: "baud",
+        args: "<rate> [none|even|odd] [1|2]",
+        help: "switch the serial port, 8N1 by default",
+        run: baud,
+    },
+    Command {
+        name: "i2c",
+        args: "[int|ext] <read|write|dump> <addr> ...",
+        help: "read <reg> [len], write <reg> <byte>... or dump registers, in hex",
+        run: i2c::run,
+    },
+    Command {
+        name: "load",
+        args: "",
+        help: "how busy the CPU was since the last time",
+        run: load,
+    },
+    Command {
+        name: "scan",
+        args: "[int|ext]",
+        help: "list the I2C addresses that answer, on both buses by default",
+        run: i2c::scan,
+    },
+]);
+
+static SERIAL: Mutex<RefCell<Option<UarteHandler<UARTE0, RX_SIZE, TX_SIZE>>>> =
+    Mutex::new(RefCell::new(None));
+
+static EVENTS: Events<4> = Events::new();
+static DATA_READY: Mutex<RefCell<Option<DataReady<4>>>> = Mutex::new(RefCell::new(None));
 
 #[entry]
 fn main() -> ! {
-    rtt_init_print!();
-    let board = microbit::Board::take().unwrap();
+    let board = common::init();
+
+    let load = Load::new(board.TIMER2);
+    let int = board.pins.p0_25.into_pullup_input().degrade();
+    let data_ready = DataReady::new(board.GPIOTE, int, &EVENTS);
+    free(|cs| *DATA_READY.borrow(cs).borrow_mut() = Some(data_ready));
+    unsafe { NVIC::unmask(pac::Interrupt::GPIOTE) };
 
-    let mut i2c = twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100);
+    let internal = twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100);
+    let internal = cortex_m::singleton!(: RefCell<twim::Twim<TWIM0>> = RefCell::new(internal)).unwrap();
+    let mut i2c = Shared::new(internal);
+    // `Board` doesn't hand out TWIM1, but nothing else uses it either.
+    let twim1 = unsafe { pac::Peripherals::steal() }.TWIM1;
+    let external = twim::Twim::new(twim1, board.i2c_external.into(), FREQUENCY_A::K100);
 
     // Smoke test
     let mut acc_id = [0u8];
@@ -44,79 +149,172 @@ fn main() -> ! {
         .unwrap();
     assert_eq!(acc_id[0], ACCELEROMETER_ID);
     assert_eq!(mag_id[0], MAGNETOMETER_ID);
+    data_ready::route(&mut i2c, Int1::DataReady).unwrap();
 
-    let mut sensor = Lsm303agr::new_with_i2c(i2c).into_mag_continuous().ok().unwrap();
+    let mut sensor = Lsm303agr::new_with_i2c(i2c.clone()).into_mag_continuous().ok().unwrap();
     sensor.init().unwrap();
     sensor
         .set_accel_odr(lsm303agr::AccelOutputDataRate::Hz50)
         .unwrap();
     sensor.set_mag_odr(lsm303agr::MagOutputDataRate::Hz50).unwrap();
 
-    let mut serial = UartePort::new(uarte::Uarte::new(
-        board.UARTE0,
-        board.uart.into(),
-        Parity::EXCLUDED,
-        Baudrate::BAUD115200,
-    ));
+    // Build with `--features flow-control` to stream to a USB serial adapter
+    // with RTS/CTS: its CTS goes to ring 1, its RTS to ring 2.
+    #[cfg(feature = "flow-control")]
+    let flow_control = Some(FlowControl {
+        cts: board.pins.p0_04.into_floating_input().degrade(),
+        rts: board.pins.p0_03.into_push_pull_output(Level::High).degrade(),
+    });
+    #[cfg(not(feature = "flow-control"))]
+    let flow_control = None;
 
+    let buffers = cortex_m::singleton!(: Buffers<RX_SIZE, TX_SIZE> = Buffers::new()).unwrap();
+    let (mut serial, handler) = BufferedUartePort::new(
+        common::uarte(board.UARTE0, board.uart, flow_control),
+        board.TIMER3,
+        buffers,
+    );
+    free(|cs| *SERIAL.borrow(cs).borrow_mut() = Some(handler));
+    unsafe { NVIC::unmask(pac::Interrupt::UARTE0_UART0) };
+
+    let mut clock = Clock::periodic(board.TIMER0);
+    clock.start(u32::MAX);
+    settings::init(board.TIMER1);
+
+    let mut state = State {
+        sensor,
+        buses: Buses {
+            internal: i2c,
+            external,
+        },
+        stream: None,
+        framing: None,
+        load,
+    };
+    let mut editor = Editor::new();
     loop {
-        match read_line(&mut serial) {
-            Ok(line) => match line.as_slice() {
-                b"accelerometer" => {
-                    for _ in 0..2 {
-                        let mut i = 0;
-                        while !sensor.accel_status().unwrap().xyz_new_data {
-                            i += 1;
-                        }
-                        let data = sensor.accel_data().unwrap();
-                        rprintln!("{} Acceleration: x {} y {} z {}", i, data.x, data.y, data.z);
-                    }
-                }
-                b"magnetometer" => {
-                    for _ in 0..2 {
-                        let mut i = 0;
-                        while !sensor.mag_status().unwrap().xyz_new_data {
-                            i += 1;
-                        }
-                        let data = sensor.mag_data().unwrap();
-                        rprintln!("{} Magnetization(?): x {} y {} z {}", i, data.x, data.y, data.z);
-                    }
-                }
-                _ => {
-                    let msg = str::from_utf8(&line).unwrap_or("<non-utf8-data>");
-                    write!(serial, "invalid command {msg:?}\r\n").unwrap();
-                }
-            },
-            Err(e) => {
-                write!(serial, "{e}\r\n").unwrap();
-            }
-        }
+        let line = read_line(&mut serial, &mut editor);
+        SHELL.run_line(&line, &mut state, &mut serial).unwrap();
         nb::block!(serial.flush()).unwrap();
+
+        if let Some(stream) = state.stream.take() {
+            stream::run(&mut state.sensor, &state.buses.internal, &mut serial, &clock, stream);
+            write!(serial, "\r\n").unwrap();
+        }
+        if let Some(framing) = state.framing.take() {
+            settings::switch(&mut serial, &clock, framing);
+        }
     }
 }
 
-fn read_line(serial: &mut UartePort<UARTE0>) -> Result<Vec<u8, 32>, &'static str> {
+#[interrupt]
+fn UARTE0_UART0() {
+    free(|cs| {
+        if let Some(handler) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            handler.handle_interrupt();
+        }
+    });
+}
+
+#[interrupt]
+fn GPIOTE() {
+    free(|cs| {
+        if let Some(data_ready) = DATA_READY.borrow(cs).borrow_mut().as_mut() {
+            data_ready.handle_interrupt();
+        }
+    });
+}
+
+fn read_line(serial: &mut Serial, editor: &mut Editor) -> Vec<u8, LINE_SIZE> {
     write!(serial, "> ").unwrap();
     nb::block!(serial.flush()).unwrap();
 
-    let mut buf = Vec::<u8, 32>::new();
-    loop {
-        let b = nb::block!(serial.read()).unwrap();
+    // The buffered port can't fail.
+    editor.read_line(&mut Sleeping(serial)).unwrap()
+}
 
-        if b == b'\r' || b == b'\n' {
-            write!(serial, "\r\n").unwrap();
-            nb::block!(serial.flush()).unwrap();
-            return Ok(buf);
-        }
+fn sensor_error<E>(_: E) -> Error<'static> {
+    Error::Failed("the sensor didn't answer")
+}
 
-        // Echo chars as they're typed.
-        write!(serial, "{}", b as char).unwrap();
-        nb::block!(serial.flush()).unwrap();
+fn accelerometer<'a>(state: &mut State, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    let count: u32 = if args.is_empty() { 2 } else { args.int("count")? };
+    let sensor = &mut state.sensor;
+    for _ in 0..count {
+        let data = next_accel(sensor, &EVENTS).map_err(sensor_error)?;
+        reading::write(out, reading::ACCELERATION, data.x, data.y, data.z)?;
+    }
+    Ok(())
+}
 
-        if let Err(_) = buf.push(b) {
-            write!(serial, "\r\n").unwrap();
-            nb::block!(serial.flush()).unwrap();
-            return Err("buf full");
-        }
+fn magnetometer<'a>(state: &mut State, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    let count: u32 = if args.is_empty() { 2 } else { args.int("count")? };
+    let sensor = &mut state.sensor;
+    for _ in 0..count {
+        let data = next_mag(sensor, &EVENTS).map_err(sensor_error)?;
+        reading::write(out, reading::MAGNETIZATION, data.x, data.y, data.z)?;
     }
+    Ok(())
+}
+
+fn stream<'a>(state: &mut State, args: &mut Args<'a>, _: &mut dyn Write) -> shell::Result<'a> {
+    let choices = [
+        ("accel", Sensors::Accelerometer),
+        ("mag", Sensors::Magnetometer),
+        ("both", Sensors::Both),
+    ];
+    let sensors = args.choice("sensor", &choices)?;
+    let hz: u32 = args.int("hz")?;
+    let format = if args.is_empty() {
+        Format::Csv
+    } else {
+        let choices = [
+            ("csv", Format::Csv),
+            ("json", Format::Json),
+            ("bin", Format::Binary),
+        ];
+        args.choice("format", &choices)?
+    };
+    // Only start once the whole line checks out.
+    args.finish()?;
+    let stream = Stream::new(sensors, hz, format).ok_or(Error::Failed(match sensors {
+        Sensors::Accelerometer => "the accelerometer runs at 1, 10, 25, 50, 100, 200 or 400 Hz",
+        Sensors::Magnetometer => "the magnetometer runs at 10, 20, 50 or 100 Hz",
+        Sensors::Both => "both run at 10, 50 or 100 Hz",
+    }))?;
+    state.stream = Some(stream);
+    Ok(())
+}
+
+fn load<'a>(state: &mut State, args: &mut Args<'a>, out: &mut dyn Write) -> shell::Result<'a> {
+    args.finish()?;
+    write!(out, "busy {}% of the time\r\n", state.load.busy())?;
+    Ok(())
+}
+
+fn baud<'a>(state: &mut State, args: &mut Args<'a>, _: &mut dyn Write) -> shell::Result<'a> {
+    let rate = args.word("rate")?;
+    let parity = if args.is_empty() {
+        ParityBit::None
+    } else {
+        let choices = [
+            ("none", ParityBit::None),
+            ("even", ParityBit::Even),
+            ("odd", ParityBit::Odd),
+        ];
+        args.choice("parity", &choices)?
+    };
+    let stop_bits = if args.is_empty() {
+        StopBits::One
+    } else {
+        args.choice("stop bits", &[("1", StopBits::One), ("2", StopBits::Two)])?
+    };
+    args.finish()?;
+    let framing = rate
+        .parse()
+        .ok()
+        .and_then(|rate| Framing::new(rate, parity, stop_bits))
+        .ok_or(Error::InvalidChoice { name: "rate", value: rate })?;
+    state.framing = Some(framing);
+    Ok(())
 }
@@ -0,0 +1,93 @@
+//! Switches the serial port to another baud rate, parity or stop bits.
+//!
+//! The terminal on the computer has to follow, e.g. with `Ctrl-A P` in
+//! minicom. If nobody presses Enter with the new settings in time the port
+//! goes back to the default ones, so a typo in `baud` doesn't leave the
+//! board unreachable until it is reset.
+
+use common::cpu;
+use common::serial::Framing;
+use core::cell::RefCell;
+use core::fmt::Write;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
+use embedded_hal::serial::Read;
+use embedded_hal::timer::{Cancel, CountDown};
+use microbit::hal::timer::{OneShot, Timer};
+use microbit::pac::{self, interrupt, TIMER1};
+
+use crate::stream::Clock;
+use crate::{Serial, SERIAL};
+
+/// How long the computer has to reconnect, in seconds.
+const TIMEOUT: u32 = 10;
+
+/// Wakes [`confirmed`] up when the time is up.
+static DEADLINE: Mutex<RefCell<Option<Timer<TIMER1, OneShot>>>> = Mutex::new(RefCell::new(None));
+
+pub fn init(timer: TIMER1) {
+    let mut timer = Timer::one_shot(timer);
+    timer.enable_interrupt();
+    free(|cs| *DEADLINE.borrow(cs).borrow_mut() = Some(timer));
+    unsafe { NVIC::unmask(pac::Interrupt::TIMER1) };
+}
+
+pub fn switch(serial: &mut Serial, clock: &Clock, framing: Framing) {
+    write!(
+        serial,
+        "switching to {}, press Enter within {} s to keep it\r\n",
+        framing, TIMEOUT
+    )
+    .unwrap();
+    apply(framing);
+
+    if confirmed(serial, clock) {
+        write!(serial, "now at {}\r\n", framing).unwrap();
+    } else {
+        apply(Framing::DEFAULT);
+        write!(serial, "\r\nno answer, back at {}\r\n", Framing::DEFAULT).unwrap();
+    }
+}
+
+fn apply(framing: Framing) {
+    free(|cs| {
+        if let Some(handler) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            handler.set_framing(framing);
+        }
+    });
+}
+
+/// Waits for Enter, asleep in between. Anything else is dropped, it is
+/// most likely noise from the terminal still using the old settings.
+fn confirmed(serial: &mut Serial, clock: &Clock) -> bool {
+    let timeout = TIMEOUT * 1_000_000;
+    let start = clock.read();
+    deadline(|timer| timer.start(timeout));
+    let confirmed = cpu::wait_for(|| match serial.read() {
+        Ok(b'\r' | b'\n') => Some(true),
+        _ if clock.read().wrapping_sub(start) >= timeout => Some(false),
+        _ => None,
+    });
+    deadline(|timer| {
+        // It can only fail if the timer isn't running anymore.
+        let _ = timer.cancel();
+    });
+    confirmed
+}
+
+fn deadline(f: impl FnOnce(&mut Timer<TIMER1, OneShot>)) {
+    free(|cs| {
+        if let Some(timer) = DEADLINE.borrow(cs).borrow_mut().as_mut() {
+            f(timer);
+        }
+    });
+}
+
+#[interrupt]
+fn TIMER1() {
+    // Only there to wake up `confirmed`, which goes by the clock. Waiting
+    // on a timer that went off resets its event.
+    deadline(|timer| {
+        let _ = timer.wait();
+    });
+}
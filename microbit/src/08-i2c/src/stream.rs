@@ -0,0 +1,263 @@
+//! Streams samples until a key is pressed, as CSV or JSON lines to look at
+//! or load into a spreadsheet, or in the binary format of the
+//! `sensor-stream` crate. Record the latter on the computer by running
+//! `cargo run -- /dev/ttyACM0 --send "stream both 100 bin" > samples.csv`
+//! in the `recorder` directory.
+//!
+//! A CSV line takes about 30 bytes, at 115200 baud that's enough for
+//! some 380 lines a second. Beyond that the accelerometer's FIFO fills up
+//! and samples get lost, which shows as a `# samples lost` line or a gap
+//! in the sequence numbers.
+
+use common::cpu;
+use common::data_ready::{self, Int1};
+use common::fifo::AccelFifo;
+use core::fmt::Write as _;
+use embedded_hal::serial::{Read, Write};
+use lsm303agr::{AccelOutputDataRate, AccelScale, MagOutputDataRate, Measurement};
+use microbit::hal::timer::{Periodic, Timer};
+use microbit::hal::twim::Twim;
+use microbit::pac::{TIMER0, TWIM0};
+use sensor_stream::{Sample, Sensor as Kind, FRAME_SIZE};
+
+use crate::i2c::Shared;
+use crate::{Sensor, Serial, EVENTS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sensors {
+    Accelerometer,
+    Magnetometer,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A comment with the units, a line with the column names and then
+    /// `time_us,sensor,x,y,z` lines.
+    Csv,
+    /// One JSON object per line, the first one with the units.
+    Json,
+    /// `sensor-stream` frames.
+    Binary,
+}
+
+/// What the `stream` command asked for.
+#[derive(Debug, Clone, Copy)]
+pub struct Stream {
+    hz: u32,
+    accel: Option<AccelOutputDataRate>,
+    mag: Option<MagOutputDataRate>,
+    format: Format,
+}
+
+impl Stream {
+    /// `None` if `sensors` can't sample at `hz`.
+    pub fn new(sensors: Sensors, hz: u32, format: Format) -> Option<Self> {
+        let accel = match sensors {
+            Sensors::Magnetometer => None,
+            _ => Some(accel_odr(hz)?),
+        };
+        let mag = match sensors {
+            Sensors::Accelerometer => None,
+            _ => Some(mag_odr(hz)?),
+        };
+        Some(Stream {
+            hz,
+            accel,
+            mag,
+            format,
+        })
+    }
+}
+
+fn accel_odr(hz: u32) -> Option<AccelOutputDataRate> {
+    Some(match hz {
+        1 => AccelOutputDataRate::Hz1,
+        10 => AccelOutputDataRate::Hz10,
+        25 => AccelOutputDataRate::Hz25,
+        50 => AccelOutputDataRate::Hz50,
+        100 => AccelOutputDataRate::Hz100,
+        200 => AccelOutputDataRate::Hz200,
+        400 => AccelOutputDataRate::Hz400,
+        _ => return None,
+    })
+}
+
+fn mag_odr(hz: u32) -> Option<MagOutputDataRate> {
+    Some(match hz {
+        10 => MagOutputDataRate::Hz10,
+        20 => MagOutputDataRate::Hz20,
+        50 => MagOutputDataRate::Hz50,
+        100 => MagOutputDataRate::Hz100,
+        _ => return None,
+    })
+}
+
+/// Free running microsecond counter.
+pub type Clock = Timer<TIMER0, Periodic>;
+
+pub fn run(
+    sensor: &mut Sensor,
+    bus: &Shared<Twim<TWIM0>>,
+    serial: &mut Serial,
+    clock: &Clock,
+    stream: Stream,
+) {
+    // The magnetometer is only checked when the accelerometer has a
+    // sample, like in `next_mag`, so the accelerometer runs anyway.
+    sensor
+        .set_accel_odr(stream.accel.unwrap_or(AccelOutputDataRate::Hz100))
+        .unwrap();
+    if let Some(odr) = stream.mag {
+        sensor.set_mag_odr(odr).unwrap();
+    }
+    header(serial, sensor, &stream);
+
+    // The accelerometer queues its samples in the FIFO, fetching them
+    // about ten times a second keeps the latency down without losing any.
+    // The magnetometer has no FIFO and needs a look at every sample.
+    let period = 1_000_000 / stream.hz;
+    let watermark = match stream.mag {
+        Some(_) => 0,
+        None => (stream.hz / 10).clamp(1, 16) as u8,
+    };
+    let (mode, scale) = (sensor.get_accel_mode(), sensor.get_accel_scale());
+    data_ready::route(&mut bus.clone(), Int1::Watermark).unwrap();
+    let mut fifo = AccelFifo::new(bus.clone(), mode, scale, watermark).unwrap();
+
+    let start = clock.read();
+    let mut sequence = 0u16;
+    loop {
+        // WTM stays set until the FIFO is read, without another edge, so
+        // it's the flag that counts and not the events.
+        if fifo.watermark_reached().unwrap() {
+            let now = clock.read();
+            let batch = fifo.read().unwrap();
+            if stream.accel.is_some() {
+                if batch.overrun {
+                    if stream.format == Format::Binary {
+                        // Skipping a number lets the recorder see the gap.
+                        sequence = sequence.wrapping_add(1);
+                    } else {
+                        write!(serial, "# samples lost\r\n").unwrap();
+                    }
+                }
+                // The newest sample is from about now, the ones before one
+                // period apart.
+                let count = batch.samples.len() as u32;
+                for (i, &data) in batch.samples.iter().enumerate() {
+                    let age = (count - 1 - i as u32) * period;
+                    let timestamp = now.wrapping_sub(start).wrapping_sub(age);
+                    send(
+                        serial,
+                        stream.format,
+                        sequence,
+                        timestamp,
+                        Kind::Accelerometer,
+                        data,
+                    );
+                    sequence = sequence.wrapping_add(1);
+                }
+            }
+            if stream.mag.is_some() && sensor.mag_status().unwrap().xyz_new_data {
+                let data = sensor.mag_data().unwrap();
+                let timestamp = now.wrapping_sub(start);
+                send(
+                    serial,
+                    stream.format,
+                    sequence,
+                    timestamp,
+                    Kind::Magnetometer,
+                    data,
+                );
+                sequence = sequence.wrapping_add(1);
+            }
+        }
+
+        // Asleep until INT1 or a key, any key stops it.
+        let key = cpu::wait_for(|| match serial.read() {
+            Ok(_) => Some(true),
+            Err(_) => EVENTS.pop().map(|_| false),
+        });
+        if key {
+            break;
+        }
+    }
+    nb::block!(serial.flush()).unwrap();
+
+    fifo.release().unwrap();
+    data_ready::route(&mut bus.clone(), Int1::DataReady).unwrap();
+    sensor.set_accel_odr(AccelOutputDataRate::Hz50).unwrap();
+    sensor.set_mag_odr(MagOutputDataRate::Hz50).unwrap();
+}
+
+fn header(serial: &mut Serial, sensor: &Sensor, stream: &Stream) {
+    let range = match sensor.get_accel_scale() {
+        AccelScale::G2 => 2,
+        AccelScale::G4 => 4,
+        AccelScale::G8 => 8,
+        AccelScale::G16 => 16,
+    };
+    match stream.format {
+        Format::Csv => {
+            write!(
+                serial,
+                "# {} Hz, time in us, accelerometer in mg (+-{} g), magnetometer in nT\r\n",
+                stream.hz, range
+            )
+            .unwrap();
+            write!(serial, "time_us,sensor,x,y,z\r\n").unwrap();
+        }
+        Format::Json => write!(
+            serial,
+            "{{\"hz\":{},\"time\":\"us\",\"accel\":{{\"unit\":\"mg\",\"range_g\":{}}},\"mag\":{{\"unit\":\"nT\"}}}}\r\n",
+            stream.hz, range
+        )
+        .unwrap(),
+        // Lets the receiver start with the first frame instead of the second.
+        Format::Binary => nb::block!(serial.write(0)).unwrap(),
+    }
+}
+
+fn send(
+    serial: &mut Serial,
+    format: Format,
+    sequence: u16,
+    timestamp: u32,
+    kind: Kind,
+    data: Measurement,
+) {
+    let name = match kind {
+        Kind::Accelerometer => "accel",
+        Kind::Magnetometer => "mag",
+    };
+    match format {
+        Format::Csv => write!(
+            serial,
+            "{},{},{},{},{}\r\n",
+            timestamp, name, data.x, data.y, data.z
+        )
+        .unwrap(),
+        Format::Json => write!(
+            serial,
+            "{{\"t\":{},\"sensor\":\"{}\",\"x\":{},\"y\":{},\"z\":{}}}\r\n",
+            timestamp, name, data.x, data.y, data.z
+        )
+        .unwrap(),
+        Format::Binary => {
+            let sample = Sample {
+                sequence,
+                timestamp,
+                sensor: kind,
+                x: data.x,
+                y: data.y,
+                z: data.z,
+            };
+            let mut frame = [0; FRAME_SIZE];
+            sample.encode(&mut frame);
+            for &b in &frame {
+                nb::block!(serial.write(b)).unwrap();
+            }
+        }
+    }
+}
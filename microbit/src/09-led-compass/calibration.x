@@ -0,0 +1,13 @@
+/* The last 4K flash page, where the compass keeps its calibration, see
+   `src/storage.rs`. FLASH in the `common` crate's `memory.x` still covers
+   it, the assertion keeps the program out. */
+MEMORY
+{
+  CALIBRATION : ORIGIN = 0x0003F000, LENGTH = 4K
+}
+
+_calibration_start = ORIGIN(CALIBRATION);
+_calibration_end = ORIGIN(CALIBRATION) + LENGTH(CALIBRATION);
+
+ASSERT(__veneer_limit <= _calibration_start, "
+ERROR(led-compass): the program runs into the calibration page");
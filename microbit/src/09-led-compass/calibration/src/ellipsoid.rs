@@ -0,0 +1,237 @@
+//! Least-squares ellipsoid fit for hard and soft iron calibration.
+//!
+//! The samples are fitted to the quadric
+//!
+//! ```text
+//! a x² + b y² + c z² + 2f yz + 2g xz + 2h xy + 2p x + 2q y + 2r z = 1
+//! ```
+//!
+//! from which we get the center of the ellipsoid (the hard iron offset) and
+//! its shape. The soft iron matrix is the square root of the shape matrix,
+//! which maps the ellipsoid back onto a sphere. Unlike the per axis scale of
+//! the CODAL algorithm this also undoes distortions that are rotated with
+//! respect to the sensor axes.
+//!
+//! The math is done in `f64`. It only runs once per calibration, so the soft
+//! float cost on the board doesn't matter.
+
+use libm::{fabs, pow, sqrt};
+
+use crate::{Correction, Quality, Vector};
+
+/// Minimum number of samples, one per unknown of the quadric.
+pub const MIN_SAMPLES: usize = 9;
+
+/// Result of [`fit_ellipsoid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EllipsoidCalibration {
+    /// Hard iron offset, the center of the fitted ellipsoid.
+    pub offset: Vector,
+    /// Soft iron correction, row major, fixed point with 1024 meaning 1.0.
+    pub soft_iron: [[i32; 3]; 3],
+    /// Radius of the sphere the data is mapped onto.
+    pub radius: u32,
+    pub quality: Quality,
+}
+
+impl Correction for EllipsoidCalibration {
+    fn correct(&self, enu: Vector) -> Vector {
+        let d = [
+            (enu.x - self.offset.x) as i64,
+            (enu.y - self.offset.y) as i64,
+            (enu.z - self.offset.z) as i64,
+        ];
+        let row = |r: [i32; 3]| {
+            ((r[0] as i64 * d[0] + r[1] as i64 * d[1] + r[2] as i64 * d[2]) >> 10) as i32
+        };
+        Vector {
+            x: row(self.soft_iron[0]),
+            y: row(self.soft_iron[1]),
+            z: row(self.soft_iron[2]),
+        }
+    }
+}
+
+/// Fits an ellipsoid to samples taken in the ENU frame.
+///
+/// Returns `None` if there are fewer than [`MIN_SAMPLES`] samples or they
+/// don't describe an ellipsoid, e.g. because they all lie in one plane.
+pub fn fit_ellipsoid(data: &[Vector]) -> Option<EllipsoidCalibration> {
+    if data.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    // Move the data around the origin and scale it to roughly unit size, the
+    // normal equations are badly conditioned otherwise.
+    let n = data.len() as f64;
+    let mut mean = [0.0; 3];
+    for point in data {
+        mean[0] += point.x as f64 / n;
+        mean[1] += point.y as f64 / n;
+        mean[2] += point.z as f64 / n;
+    }
+    let mut norm = 0.0;
+    for point in data {
+        for (v, m) in [point.x, point.y, point.z].iter().zip(mean.iter()) {
+            norm = f64::max(norm, fabs(*v as f64 - m));
+        }
+    }
+    if norm == 0.0 {
+        return None;
+    }
+
+    // Accumulate the normal equations `D^T D v = D^T 1`.
+    let mut ata = [[0.0; 9]; 9];
+    let mut atb = [0.0; 9];
+    for point in data {
+        let x = (point.x as f64 - mean[0]) / norm;
+        let y = (point.y as f64 - mean[1]) / norm;
+        let z = (point.z as f64 - mean[2]) / norm;
+        let row = [
+            x * x,
+            y * y,
+            z * z,
+            2.0 * y * z,
+            2.0 * x * z,
+            2.0 * x * y,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+        ];
+        for i in 0..9 {
+            for j in 0..9 {
+                ata[i][j] += row[i] * row[j];
+            }
+            atb[i] += row[i];
+        }
+    }
+    let [a, b, c, f, g, h, p, q, r] = solve(ata, atb)?;
+
+    let shape = [[a, h, g], [h, b, f], [g, f, c]];
+    let center = solve(shape, [-p, -q, -r])?;
+
+    // Shift the quadric to the center: (x - c)^T A (x - c) = 1 + c^T A c
+    let mut k = 1.0;
+    for i in 0..3 {
+        for j in 0..3 {
+            k += center[i] * shape[i][j] * center[j];
+        }
+    }
+
+    let (mut values, vectors) = eigen(shape);
+    let mut det = 1.0;
+    for value in values.iter_mut() {
+        *value /= k;
+        if *value <= 0.0 {
+            return None;
+        }
+        det *= *value;
+    }
+
+    // Map onto a sphere with the same volume as the ellipsoid, so the field
+    // strength stays about the same.
+    let radius = pow(det, -1.0 / 6.0);
+
+    // soft_iron = radius * V * sqrt(diag(values)) * V^T
+    let mut soft_iron = [[0; 3]; 3];
+    for (i, out) in soft_iron.iter_mut().enumerate() {
+        for (j, out) in out.iter_mut().enumerate() {
+            let mut m = 0.0;
+            for (l, value) in values.iter().enumerate() {
+                m += vectors[i][l] * sqrt(*value) * vectors[j][l];
+            }
+            *out = (1024.0 * radius * m) as i32;
+        }
+    }
+
+    let mut calibration = EllipsoidCalibration {
+        offset: Vector {
+            x: (mean[0] + norm * center[0]) as i32,
+            y: (mean[1] + norm * center[1]) as i32,
+            z: (mean[2] + norm * center[2]) as i32,
+        },
+        soft_iron,
+        radius: (norm * radius) as u32,
+        quality: Quality::default(),
+    };
+    calibration.quality =
+        Quality::measure(&calibration, calibration.offset, calibration.radius, data);
+    Some(calibration)
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting.
+fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| fabs(a[i][col]).total_cmp(&fabs(a[j][col])))?;
+        if fabs(a[pivot][col]) < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col];
+        for row in col + 1..N {
+            let factor = a[row][col] / pivot_row[col];
+            for k in col..N {
+                a[row][k] -= factor * pivot_row[k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let mut sum = b[row];
+        for k in row + 1..N {
+            sum -= a[row][k] * x[k];
+        }
+        x[row] = sum / a[row][row];
+    }
+    Some(x)
+}
+
+/// Eigen decomposition of a symmetric 3x3 matrix with the Jacobi method.
+///
+/// Returns the eigenvalues and a matrix with the eigenvectors as columns.
+fn eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..50 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off < 1e-24 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+
+            // Rotation that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (fabs(theta) + sqrt(theta * theta + 1.0));
+            let c = 1.0 / sqrt(t * t + 1.0);
+            let s = t * c;
+
+            for row in a.iter_mut() {
+                let akp = row[p];
+                let akq = row[q];
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            for k in 0..3 {
+                a[p][k] = c * row_p[k] - s * row_q[k];
+                a[q][k] = s * row_p[k] + c * row_q[k];
+            }
+            for row in v.iter_mut() {
+                let vp = row[p];
+                let vq = row[q];
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
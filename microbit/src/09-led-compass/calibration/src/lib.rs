@@ -0,0 +1,309 @@
+//! Magnetometer calibration for the micro:bit LED compass.
+//!
+//! Translated from <https://github.com/lancaster-university/codal-microbit-v2/blob/006abf5566774fbcf674c0c7df27e8a9d20013de/source/MicroBitCompassCalibrator.cpp>
+//!
+//! Everything in here is plain arithmetic on [`Vector`]s, so it runs (and is
+//! tested) on the host as well as on the board. Collecting the samples is up
+//! to the caller, see `src/calibration.rs` in the chapter.
+//!
+//! Two algorithms are available: the CODAL grid search in [`calibrate`], and
+//! the ellipsoid fit in [`fit_ellipsoid`] which can also correct rotated soft
+//! iron distortion. [`calibrate_with`] picks one at runtime, and
+//! [`online::OnlineCalibration`] keeps rerunning it while the compass is used.
+
+#![no_std]
+
+use libm::{fabsf, sqrtf};
+use lsm303agr::Measurement;
+
+mod ellipsoid;
+pub mod online;
+pub mod quality;
+pub mod record;
+
+pub use ellipsoid::{fit_ellipsoid, EllipsoidCalibration, MIN_SAMPLES};
+pub use quality::{Quality, Rejection, Thresholds};
+
+/// Step size of the grid search in [`calibrate`].
+pub const CALIBRATION_INCREMENT: i32 = 200;
+
+/// A three axis reading, in the same units as the sensor (nT).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Vector {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Vector {
+    pub const fn new(x: i32, y: i32, z: i32) -> Vector {
+        Vector { x, y, z }
+    }
+}
+
+impl From<Measurement> for Vector {
+    fn from(measurement: Measurement) -> Vector {
+        Vector {
+            x: measurement.x,
+            y: measurement.y,
+            z: measurement.z,
+        }
+    }
+}
+
+impl From<Vector> for Measurement {
+    fn from(vector: Vector) -> Measurement {
+        Measurement {
+            x: vector.x,
+            y: vector.y,
+            z: vector.z,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub center: Vector,
+    /// Per axis scale, fixed point with 1024 meaning 1.0.
+    pub scale: Vector,
+    pub radius: u32,
+    pub quality: Quality,
+}
+
+impl Correction for Calibration {
+    fn correct(&self, enu: Vector) -> Vector {
+        Vector {
+            x: ((enu.x - self.center.x) * self.scale.x) >> 10,
+            y: ((enu.y - self.center.y) * self.scale.y) >> 10,
+            z: ((enu.z - self.center.z) * self.scale.z) >> 10,
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration {
+            center: Vector { x: 0, y: 0, z: 0 },
+            scale: Vector {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 0,
+            quality: Quality::default(),
+        }
+    }
+}
+
+fn difference_square(a: Vector, b: Vector) -> f32 {
+    let dx = (a.x - b.x) as f32;
+    let dy = (a.y - b.y) as f32;
+    let dz = (a.z - b.z) as f32;
+
+    (dx * dx) + (dy * dy) + (dz * dz)
+}
+
+/// Spread between the closest and the furthest point from `center`, lower is
+/// better.
+pub fn measure_score(center: Vector, data: &[Vector]) -> f32 {
+    let mut min_d = difference_square(center, data[0]);
+    let mut max_d = min_d;
+
+    for point in data[1..].iter() {
+        let d = difference_square(center, *point);
+        if d < min_d {
+            min_d = d;
+        }
+
+        if d > max_d {
+            max_d = d;
+        }
+    }
+
+    max_d - min_d
+}
+
+/// Computes a calibration from samples taken in the ENU frame, see
+/// [`measurement_to_enu`].
+pub fn calibrate(data: &[Vector]) -> Calibration {
+    // Approximate a center for the data
+    let mut center = Vector { x: 0, y: 0, z: 0 };
+
+    for point in data {
+        center.x += point.x;
+        center.y += point.y;
+        center.z += point.z;
+    }
+
+    center.x /= data.len() as i32;
+    center.y /= data.len() as i32;
+    center.z /= data.len() as i32;
+
+    let mut current = center;
+    let mut best = current;
+    let mut score = measure_score(current, data);
+
+    // Calculate a fixpoint position
+    loop {
+        for x in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
+            for y in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
+                for z in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
+                    let mut attempt = current;
+                    attempt.x += x;
+                    attempt.y += y;
+                    attempt.z += z;
+
+                    let attempt_score = measure_score(attempt, data);
+                    if attempt_score < score {
+                        score = attempt_score;
+                        best = attempt;
+                    }
+                }
+            }
+        }
+
+        if best == current {
+            break;
+        }
+
+        current = best;
+    }
+
+    spherify(current, data)
+}
+
+/// Computes the per axis scale that stretches the data around `center` into
+/// a sphere.
+pub fn spherify(center: Vector, data: &[Vector]) -> Calibration {
+    let mut radius = 0;
+    for point in data {
+        let d = sqrtf(difference_square(center, *point)) as u32;
+        if d > radius {
+            radius = d;
+        }
+    }
+
+    let mut scale: f32 = 0.0;
+    let mut weight_x = 0.0;
+    let mut weight_y = 0.0;
+    let mut weight_z = 0.0;
+
+    for point in data {
+        let d = sqrtf(difference_square(center, *point));
+        let s = (radius as f32 / d) - 1.0;
+        scale = scale.max(s);
+
+        let dx = point.x - center.x;
+        let dy = point.y - center.y;
+        let dz = point.z - center.z;
+
+        weight_x += s * fabsf(dx as f32 / d);
+        weight_y += s * fabsf(dy as f32 / d);
+        weight_z += s * fabsf(dz as f32 / d);
+    }
+
+    let wmag = sqrtf((weight_x * weight_x) + (weight_y * weight_y) + (weight_z * weight_z));
+    let scale_x = 1.0 + scale * (weight_x / wmag);
+    let scale_y = 1.0 + scale * (weight_y / wmag);
+    let scale_z = 1.0 + scale * (weight_z / wmag);
+
+    let mut calibration = Calibration {
+        center,
+        radius,
+        scale: Vector {
+            x: (1024.0 * scale_x) as i32,
+            y: (1024.0 * scale_y) as i32,
+            z: (1024.0 * scale_z) as i32,
+        },
+        quality: Quality::default(),
+    };
+    calibration.quality = Quality::measure(&calibration, center, radius, data);
+    calibration
+}
+
+/// Which algorithm [`calibrate_with`] uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Grid search for the center plus per axis scale, see [`calibrate`].
+    Codal,
+    /// Hard iron offset plus full soft iron matrix, see [`fit_ellipsoid`].
+    Ellipsoid,
+}
+
+/// Result of either algorithm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fit {
+    Codal(Calibration),
+    Ellipsoid(EllipsoidCalibration),
+}
+
+impl Fit {
+    /// The hard iron offset, i.e. the center of the fitted sphere or
+    /// ellipsoid.
+    pub fn center(&self) -> Vector {
+        match self {
+            Fit::Codal(calibration) => calibration.center,
+            Fit::Ellipsoid(calibration) => calibration.offset,
+        }
+    }
+
+    pub fn quality(&self) -> Quality {
+        match self {
+            Fit::Codal(calibration) => calibration.quality,
+            Fit::Ellipsoid(calibration) => calibration.quality,
+        }
+    }
+}
+
+impl Correction for Fit {
+    fn correct(&self, enu: Vector) -> Vector {
+        match self {
+            Fit::Codal(calibration) => calibration.correct(enu),
+            Fit::Ellipsoid(calibration) => calibration.correct(enu),
+        }
+    }
+}
+
+/// Calibrates with the given algorithm.
+///
+/// The ellipsoid fit falls back to the CODAL algorithm if the samples don't
+/// describe an ellipsoid.
+pub fn calibrate_with(algorithm: Algorithm, data: &[Vector]) -> Fit {
+    match algorithm {
+        Algorithm::Codal => Fit::Codal(calibrate(data)),
+        Algorithm::Ellipsoid => match fit_ellipsoid(data) {
+            Some(calibration) => Fit::Ellipsoid(calibration),
+            None => Fit::Codal(calibrate(data)),
+        },
+    }
+}
+
+/// Something that turns a reading in the ENU frame into a calibrated one.
+pub trait Correction {
+    fn correct(&self, enu: Vector) -> Vector;
+}
+
+/// Applies `calibration` to a raw sensor reading and returns it in cartesian
+/// coordinates (x right, y up, z out of the board).
+pub fn calibrated_measurement<C>(measurement: Vector, calibration: &C) -> Vector
+where
+    C: Correction + ?Sized,
+{
+    enu_to_cartesian(calibration.correct(measurement_to_enu(measurement)))
+}
+
+pub fn measurement_to_enu(measurement: Vector) -> Vector {
+    Vector {
+        x: -measurement.y,
+        y: -measurement.x,
+        z: measurement.z,
+    }
+}
+
+pub fn enu_to_cartesian(measurement: Vector) -> Vector {
+    Vector {
+        x: -measurement.y,
+        y: measurement.x,
+        z: measurement.z,
+    }
+}
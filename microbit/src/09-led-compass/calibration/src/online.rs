@@ -0,0 +1,157 @@
+//! Keeps refining the calibration in the background while the compass is in
+//! use.
+//!
+//! Every reading is offered to an [`OnlineCalibration`], which keeps at most
+//! `N` of them. A reading that is close to one we already have replaces it,
+//! so the set stays spread out instead of filling up with whatever direction
+//! the board happens to point at, and old readings get refreshed. Otherwise
+//! the oldest reading makes room for it. Once the readings cover enough of
+//! the sphere the fit is run again, which lets the calibration follow the
+//! board when e.g. the battery pack or a magnet nearby moves. Fits that don't
+//! pass the quality [`Thresholds`] are thrown away.
+
+use crate::quality::{self, Thresholds};
+use crate::{calibrate_with, Algorithm, Fit, Vector};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub algorithm: Algorithm,
+    /// Readings closer than this to a stored one replace it (nT).
+    pub min_spacing: i32,
+    /// Out of [`quality::DIRECTIONS`], needed before fitting.
+    pub min_coverage: u32,
+    /// New readings to collect between two fits.
+    pub refit_after: u32,
+    /// Refits that don't pass these are dropped.
+    pub thresholds: Thresholds,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            algorithm: Algorithm::Codal,
+            min_spacing: 8_000,
+            min_coverage: 20,
+            refit_after: 8,
+            thresholds: Thresholds::default(),
+        }
+    }
+}
+
+pub struct OnlineCalibration<const N: usize> {
+    settings: Settings,
+    samples: [Vector; N],
+    /// Value of `clock` when the sample was stored, for finding the oldest.
+    stored_at: [u32; N],
+    len: usize,
+    clock: u32,
+    new_samples: u32,
+    fit: Option<Fit>,
+}
+
+impl<const N: usize> OnlineCalibration<N> {
+    pub fn new(settings: Settings) -> Self {
+        OnlineCalibration {
+            settings,
+            samples: [Vector::default(); N],
+            stored_at: [0; N],
+            len: 0,
+            clock: 0,
+            new_samples: 0,
+            fit: None,
+        }
+    }
+
+    /// Starts from an existing calibration, e.g. the one loaded from flash.
+    /// Its center is used to judge coverage until the first refit.
+    pub fn with_fit(settings: Settings, fit: Fit) -> Self {
+        let mut online = Self::new(settings);
+        online.fit = Some(fit);
+        online
+    }
+
+    /// Offers a reading in the ENU frame. Returns the new calibration if this
+    /// reading triggered a refit.
+    pub fn add(&mut self, enu: Vector) -> Option<Fit> {
+        self.clock = self.clock.wrapping_add(1);
+        self.store(enu);
+        self.new_samples = self.new_samples.saturating_add(1);
+
+        if self.new_samples < self.settings.refit_after
+            || self.coverage() < self.settings.min_coverage
+        {
+            return None;
+        }
+
+        self.new_samples = 0;
+        let fit = calibrate_with(self.settings.algorithm, self.samples());
+        fit.quality().check(&self.settings.thresholds).ok()?;
+        self.fit = Some(fit);
+        Some(fit)
+    }
+
+    fn store(&mut self, enu: Vector) {
+        let nearest = (0..self.len).min_by_key(|&i| distance_square(self.samples[i], enu));
+        let spacing = self.settings.min_spacing as i64;
+        let slot = match nearest {
+            Some(i) if distance_square(self.samples[i], enu) < spacing * spacing => i,
+            _ if self.len < N => {
+                self.len += 1;
+                self.len - 1
+            }
+            // Full and nothing close, make room by dropping the oldest one.
+            _ => (0..N)
+                .max_by_key(|&i| self.clock.wrapping_sub(self.stored_at[i]))
+                .unwrap(),
+        };
+        self.samples[slot] = enu;
+        self.stored_at[slot] = self.clock;
+    }
+
+    /// The readings currently kept.
+    pub fn samples(&self) -> &[Vector] {
+        &self.samples[..self.len]
+    }
+
+    /// The latest calibration, if any.
+    pub fn fit(&self) -> Option<Fit> {
+        self.fit
+    }
+
+    /// In how many of the [`quality::DIRECTIONS`] seen from the current center there
+    /// is at least one reading.
+    pub fn coverage(&self) -> u32 {
+        if self.len == 0 {
+            return 0;
+        }
+
+        let center = match self.fit {
+            Some(fit) => fit.center(),
+            None => mean(self.samples()),
+        };
+
+        quality::coverage(center, self.samples())
+    }
+}
+
+fn distance_square(a: Vector, b: Vector) -> i64 {
+    let dx = (a.x - b.x) as i64;
+    let dy = (a.y - b.y) as i64;
+    let dz = (a.z - b.z) as i64;
+    dx * dx + dy * dy + dz * dz
+}
+
+fn mean(data: &[Vector]) -> Vector {
+    let (mut x, mut y, mut z) = (0i64, 0i64, 0i64);
+    for point in data {
+        x += point.x as i64;
+        y += point.y as i64;
+        z += point.z as i64;
+    }
+    let n = data.len() as i64;
+    Vector {
+        x: (x / n) as i32,
+        y: (y / n) as i32,
+        z: (z / n) as i32,
+    }
+}
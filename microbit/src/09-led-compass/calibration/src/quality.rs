@@ -0,0 +1,159 @@
+//! How good a calibration is, so bad runs can be thrown away.
+
+use core::fmt;
+use libm::sqrtf;
+
+use crate::{Correction, Vector};
+
+/// Number of directions [`coverage`] tells apart: the faces, edges and
+/// corners of a cube.
+pub const DIRECTIONS: u32 = 26;
+
+/// Strength of the Earth's magnetic field we expect to see (nT). It is
+/// between 25 and 65 µT depending on where you are.
+pub const EXPECTED_FIELD: f32 = 50_000.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// Difference between the longest and the shortest calibrated sample,
+    /// relative to their mean length. 0 for a perfect sphere.
+    pub spread: f32,
+    /// Percentage of the sphere around the center that has samples.
+    pub coverage: u8,
+    /// Fitted radius divided by [`EXPECTED_FIELD`].
+    pub field_ratio: f32,
+}
+
+impl Quality {
+    /// Measures how well `calibration` maps `data` (ENU frame) onto a sphere
+    /// of `radius` around `center`.
+    pub fn measure<C>(calibration: &C, center: Vector, radius: u32, data: &[Vector]) -> Quality
+    where
+        C: Correction + ?Sized,
+    {
+        let mut min = f32::MAX;
+        let mut max = 0.0f32;
+        let mut sum = 0.0;
+        for point in data {
+            let c = calibration.correct(*point);
+            let (x, y, z) = (c.x as f32, c.y as f32, c.z as f32);
+            let len = sqrtf(x * x + y * y + z * z);
+            min = min.min(len);
+            max = max.max(len);
+            sum += len;
+        }
+        let mean = sum / data.len() as f32;
+
+        Quality {
+            spread: if mean > 0.0 { (max - min) / mean } else { 0.0 },
+            coverage: (coverage(center, data) * 100 / DIRECTIONS) as u8,
+            field_ratio: radius as f32 / EXPECTED_FIELD,
+        }
+    }
+
+    /// Checks against `thresholds`, returning the first one that failed.
+    pub fn check(&self, thresholds: &Thresholds) -> Result<(), Rejection> {
+        if self.spread > thresholds.max_spread {
+            Err(Rejection::Spread)
+        } else if self.coverage < thresholds.min_coverage {
+            Err(Rejection::Coverage)
+        } else if self.field_ratio < thresholds.min_field_ratio
+            || self.field_ratio > thresholds.max_field_ratio
+        {
+            Err(Rejection::Field)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "spread {:.1}%, coverage {}%, field {:.0}% of expected",
+            self.spread * 100.0,
+            self.coverage,
+            self.field_ratio * 100.0
+        )
+    }
+}
+
+/// Limits for [`Quality::check`].
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub max_spread: f32,
+    pub min_coverage: u8,
+    pub min_field_ratio: f32,
+    pub max_field_ratio: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            max_spread: 0.15,
+            // The tilt game doesn't get you all the way around
+            min_coverage: 35,
+            min_field_ratio: 0.4,
+            max_field_ratio: 1.4,
+        }
+    }
+}
+
+/// Why [`Quality::check`] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The calibrated samples are still far from a sphere.
+    Spread,
+    /// Too few directions were sampled.
+    Coverage,
+    /// The field is much weaker or stronger than the Earth's, there is
+    /// probably a magnet nearby.
+    Field,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Rejection::Spread => "samples don't fit a sphere",
+            Rejection::Coverage => "not enough directions sampled",
+            Rejection::Field => "field strength is off, move away from magnets",
+        })
+    }
+}
+
+/// In how many of the [`DIRECTIONS`] seen from `center` there is at least
+/// one sample.
+pub fn coverage(center: Vector, data: &[Vector]) -> u32 {
+    let mut seen = 0u32;
+    for point in data {
+        let d = [
+            (point.x - center.x) as f32,
+            (point.y - center.y) as f32,
+            (point.z - center.z) as f32,
+        ];
+        let len = sqrtf(d[0] * d[0] + d[1] * d[1] + d[2] * d[2]);
+        if len == 0.0 {
+            continue;
+        }
+
+        // Each axis is negative, about zero or positive. With a third as
+        // the limit all 26 directions cover about the same area, and as
+        // every unit vector has a component of at least 1/sqrt(3) the all
+        // zero bin in the middle is never hit.
+        let mut bin = 0;
+        for c in d.iter() {
+            let c = c / len;
+            bin = bin * 3
+                + if c < -1.0 / 3.0 {
+                    0
+                } else if c > 1.0 / 3.0 {
+                    2
+                } else {
+                    1
+                };
+        }
+        seen |= 1 << bin;
+    }
+    seen.count_ones()
+}
@@ -0,0 +1,147 @@
+//! Fixed size binary record for keeping a [`Fit`] in flash.
+//!
+//! All fields are little endian `u32`/`i32` words:
+//!
+//! | word   | content                                        |
+//! |--------|------------------------------------------------|
+//! | 0      | [`MAGIC`]                                      |
+//! | 1      | algorithm, 0 for CODAL and 1 for the ellipsoid |
+//! | 2..15  | the calibration, zero padded                   |
+//! | 15..18 | its [`Quality`]                                |
+//! | 18     | CRC-32 of words 0 to 17                        |
+//!
+//! Erased flash reads as all ones, so it never passes the magic check.
+
+use crate::{Calibration, EllipsoidCalibration, Fit, Quality, Vector};
+
+/// "CAL2", bump the digit when the layout changes.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"CAL2");
+
+/// Size of an encoded record in bytes.
+pub const RECORD_LEN: usize = WORDS * 4;
+
+const WORDS: usize = 19;
+const PAYLOAD: usize = 13;
+const QUALITY: usize = 2 + PAYLOAD;
+
+const CODAL: u32 = 0;
+const ELLIPSOID: u32 = 1;
+
+/// Serializes `fit` into a record.
+pub fn encode(fit: &Fit) -> [u8; RECORD_LEN] {
+    let mut words = [0u32; WORDS];
+    words[0] = MAGIC;
+    words[1] = match fit {
+        Fit::Codal(_) => CODAL,
+        Fit::Ellipsoid(_) => ELLIPSOID,
+    };
+
+    put_quality(&mut words[QUALITY..QUALITY + 3], fit.quality());
+
+    let payload = &mut words[2..2 + PAYLOAD];
+    match fit {
+        Fit::Codal(calibration) => {
+            put_vector(&mut payload[0..3], calibration.center);
+            put_vector(&mut payload[3..6], calibration.scale);
+            payload[6] = calibration.radius;
+        }
+        Fit::Ellipsoid(calibration) => {
+            put_vector(&mut payload[0..3], calibration.offset);
+            for (i, row) in calibration.soft_iron.iter().enumerate() {
+                for (j, m) in row.iter().enumerate() {
+                    payload[3 + 3 * i + j] = *m as u32;
+                }
+            }
+            payload[12] = calibration.radius;
+        }
+    }
+    words[WORDS - 1] = crc32(&words[..WORDS - 1]);
+
+    let mut bytes = [0; RECORD_LEN];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(words.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+/// Deserializes a record, returns `None` unless it is valid.
+pub fn decode(bytes: &[u8; RECORD_LEN]) -> Option<Fit> {
+    let mut words = [0u32; WORDS];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+
+    if words[0] != MAGIC || words[WORDS - 1] != crc32(&words[..WORDS - 1]) {
+        return None;
+    }
+
+    let quality = get_quality(&words[QUALITY..QUALITY + 3]);
+
+    let payload = &words[2..2 + PAYLOAD];
+    match words[1] {
+        CODAL => Some(Fit::Codal(Calibration {
+            center: get_vector(&payload[0..3]),
+            scale: get_vector(&payload[3..6]),
+            radius: payload[6],
+            quality,
+        })),
+        ELLIPSOID => {
+            let mut soft_iron = [[0; 3]; 3];
+            for (i, row) in soft_iron.iter_mut().enumerate() {
+                for (j, m) in row.iter_mut().enumerate() {
+                    *m = payload[3 + 3 * i + j] as i32;
+                }
+            }
+            Some(Fit::Ellipsoid(EllipsoidCalibration {
+                offset: get_vector(&payload[0..3]),
+                soft_iron,
+                radius: payload[12],
+                quality,
+            }))
+        }
+        _ => None,
+    }
+}
+
+fn put_vector(words: &mut [u32], vector: Vector) {
+    words[0] = vector.x as u32;
+    words[1] = vector.y as u32;
+    words[2] = vector.z as u32;
+}
+
+fn get_vector(words: &[u32]) -> Vector {
+    Vector {
+        x: words[0] as i32,
+        y: words[1] as i32,
+        z: words[2] as i32,
+    }
+}
+
+fn put_quality(words: &mut [u32], quality: Quality) {
+    words[0] = quality.spread.to_bits();
+    words[1] = quality.coverage as u32;
+    words[2] = quality.field_ratio.to_bits();
+}
+
+fn get_quality(words: &[u32]) -> Quality {
+    Quality {
+        spread: f32::from_bits(words[0]),
+        coverage: words[1] as u8,
+        field_ratio: f32::from_bits(words[2]),
+    }
+}
+
+/// CRC-32 (IEEE 802.3) over the little endian bytes of `words`.
+fn crc32(words: &[u32]) -> u32 {
+    let mut crc = !0u32;
+    for word in words {
+        for byte in word.to_le_bytes() {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+    !crc
+}
@@ -0,0 +1,49 @@
+//! Point clouds shared by the tests.
+
+#![allow(dead_code)]
+
+use compass_calibration::Vector;
+
+/// `n` points spread evenly over a unit sphere, from the top down.
+pub fn fibonacci_sphere(n: usize) -> Vec<[f64; 3]> {
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    (0..n)
+        .map(|i| {
+            let z = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+            let r = (1.0 - z * z).sqrt();
+            let theta = golden_angle * i as f64;
+            [r * theta.cos(), r * theta.sin(), z]
+        })
+        .collect()
+}
+
+/// Samples an axis aligned ellipsoid with the given semi axes around `center`.
+pub fn ellipsoid(n: usize, center: Vector, axes: [f64; 3]) -> Vec<Vector> {
+    fibonacci_sphere(n)
+        .into_iter()
+        .map(|p| scaled(p, center, axes))
+        .collect()
+}
+
+/// `n` points on a sphere of `radius` around `center`.
+pub fn sphere(n: usize, center: Vector, radius: f64) -> Vec<Vector> {
+    ellipsoid(n, center, [radius; 3])
+}
+
+/// Like [`sphere`], but only the points with `z >= min_z`, a cap instead of
+/// the whole sphere.
+pub fn cap(n: usize, center: Vector, radius: f64, min_z: f64) -> Vec<Vector> {
+    fibonacci_sphere(n)
+        .into_iter()
+        .filter(|p| p[2] >= min_z)
+        .map(|p| scaled(p, center, [radius; 3]))
+        .collect()
+}
+
+fn scaled(p: [f64; 3], center: Vector, axes: [f64; 3]) -> Vector {
+    Vector::new(
+        center.x + (p[0] * axes[0]) as i32,
+        center.y + (p[1] * axes[1]) as i32,
+        center.z + (p[2] * axes[2]) as i32,
+    )
+}
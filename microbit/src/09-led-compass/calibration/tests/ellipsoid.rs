@@ -0,0 +1,169 @@
+//! Checks the ellipsoid fit against point clouds with a known hard iron
+//! offset and soft iron distortion.
+
+use compass_calibration::{
+    calibrate, calibrate_with, calibrated_measurement, fit_ellipsoid, measurement_to_enu,
+    Algorithm, Correction, Fit, Vector,
+};
+
+mod common;
+use common::fibonacci_sphere;
+
+const EARTH_RADIUS: f64 = 42_000.0;
+
+/// Distorts a sphere of `EARTH_RADIUS` by `matrix` and moves it to `offset`.
+fn distorted(n: usize, matrix: [[f64; 3]; 3], offset: Vector) -> Vec<Vector> {
+    let offset = [offset.x as f64, offset.y as f64, offset.z as f64];
+    fibonacci_sphere(n)
+        .into_iter()
+        .map(|p| {
+            let mut out = [0; 3];
+            for i in 0..3 {
+                let d: f64 = (0..3).map(|j| matrix[i][j] * p[j]).sum();
+                out[i] = (offset[i] + EARTH_RADIUS * d) as i32;
+            }
+            Vector::new(out[0], out[1], out[2])
+        })
+        .collect()
+}
+
+/// Scales by 1.3, 0.9 and 0.75 along axes rotated 30° around z and then 20°
+/// around x, which the per axis scale can't undo.
+fn rotated_soft_iron() -> [[f64; 3]; 3] {
+    let (s1, c1) = 30f64.to_radians().sin_cos();
+    let (s2, c2) = 20f64.to_radians().sin_cos();
+    let rz = [[c1, -s1, 0.0], [s1, c1, 0.0], [0.0, 0.0, 1.0]];
+    let rx = [[1.0, 0.0, 0.0], [0.0, c2, -s2], [0.0, s2, c2]];
+    let rot = mul(rx, rz);
+    let scale = [[1.3, 0.0, 0.0], [0.0, 0.9, 0.0], [0.0, 0.0, 0.75]];
+    mul(mul(rot, scale), transpose(rot))
+}
+
+fn mul(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i][j] = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transpose(a: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i][j] = a[j][i];
+        }
+    }
+    out
+}
+
+fn length(v: Vector) -> f64 {
+    let (x, y, z) = (v.x as f64, v.y as f64, v.z as f64);
+    (x * x + y * y + z * z).sqrt()
+}
+
+/// Ratio between the longest and the shortest calibrated vector.
+fn spread<C: Correction>(data: &[Vector], calibration: &C) -> f64 {
+    let lengths: Vec<f64> = data
+        .iter()
+        .map(|&enu| length(calibrated_measurement(measurement_to_enu(enu), calibration)))
+        .collect();
+    let max = lengths.iter().cloned().fold(f64::MIN, f64::max);
+    let min = lengths.iter().cloned().fold(f64::MAX, f64::min);
+    max / min
+}
+
+fn assert_offset(got: Vector, want: Vector, tolerance: i32) {
+    for (g, w) in [(got.x, want.x), (got.y, want.y), (got.z, want.z)] {
+        assert!(
+            (g - w).abs() <= tolerance,
+            "offset {:?} too far from {:?}",
+            got,
+            want
+        );
+    }
+}
+
+#[test]
+fn sphere_gives_identity() {
+    let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    let offset = Vector::new(-24_728, 32_424, 86_592);
+    let data = distorted(25, identity, offset);
+    let calibration = fit_ellipsoid(&data).unwrap();
+
+    assert_offset(calibration.offset, offset, 10);
+    assert!((calibration.radius as f64 - EARTH_RADIUS).abs() < 0.01 * EARTH_RADIUS);
+    for (i, row) in calibration.soft_iron.iter().enumerate() {
+        for (j, &m) in row.iter().enumerate() {
+            let want = if i == j { 1024 } else { 0 };
+            assert!(
+                (m - want).abs() <= 5,
+                "soft iron {:?}",
+                calibration.soft_iron
+            );
+        }
+    }
+}
+
+#[test]
+fn rotated_soft_iron_is_corrected() {
+    let offset = Vector::new(10_000, -20_000, 40_000);
+    let data = distorted(25, rotated_soft_iron(), offset);
+
+    let ellipsoid = fit_ellipsoid(&data).unwrap();
+    assert_offset(ellipsoid.offset, offset, 50);
+    assert!(spread(&data, &ellipsoid) < 1.01);
+
+    // The per axis scale only gets part of the way there.
+    let codal = calibrate(&data);
+    assert!(spread(&data, &codal) > 1.1);
+}
+
+#[test]
+fn soft_iron_matrix_is_symmetric() {
+    let data = distorted(50, rotated_soft_iron(), Vector::new(0, 0, 0));
+    let m = fit_ellipsoid(&data).unwrap().soft_iron;
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((m[i][j] - m[j][i]).abs() <= 1, "soft iron {:?}", m);
+        }
+    }
+}
+
+#[test]
+fn too_few_samples() {
+    let data = distorted(8, rotated_soft_iron(), Vector::new(0, 0, 0));
+    assert_eq!(fit_ellipsoid(&data), None);
+}
+
+#[test]
+fn flat_samples_fall_back_to_codal() {
+    // Only turned around z, so every sample is in the same plane.
+    let data: Vec<Vector> = (0..25)
+        .map(|i| {
+            let (s, c) = (i as f64 * std::f64::consts::TAU / 25.0).sin_cos();
+            Vector::new((EARTH_RADIUS * c) as i32, (EARTH_RADIUS * s) as i32, 5_000)
+        })
+        .collect();
+
+    assert_eq!(fit_ellipsoid(&data), None);
+    assert!(matches!(
+        calibrate_with(Algorithm::Ellipsoid, &data),
+        Fit::Codal(_)
+    ));
+}
+
+#[test]
+fn calibrate_with_selects_algorithm() {
+    let data = distorted(25, rotated_soft_iron(), Vector::new(1_000, 2_000, 3_000));
+    assert_eq!(
+        calibrate_with(Algorithm::Codal, &data),
+        Fit::Codal(calibrate(&data))
+    );
+    assert_eq!(
+        calibrate_with(Algorithm::Ellipsoid, &data),
+        Fit::Ellipsoid(fit_ellipsoid(&data).unwrap())
+    );
+}
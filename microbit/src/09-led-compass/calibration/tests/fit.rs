@@ -0,0 +1,123 @@
+//! Feeds synthetic point clouds through the calibration and checks that the
+//! shape they were generated from is recovered.
+
+use compass_calibration::{
+    calibrate, calibrated_measurement, measure_score, measurement_to_enu, Calibration, Vector,
+    CALIBRATION_INCREMENT,
+};
+use lsm303agr::Measurement;
+
+mod common;
+use common::{ellipsoid, sphere};
+
+/// Roughly what the board in `src/main.rs` measured.
+const EARTH_RADIUS: f64 = 42_000.0;
+
+fn length(v: Vector) -> f32 {
+    let (x, y, z) = (v.x as f32, v.y as f32, v.z as f32);
+    (x * x + y * y + z * z).sqrt()
+}
+
+/// Ratio between the longest and the shortest calibrated vector.
+fn spread(data: &[Vector], calibration: &Calibration) -> f32 {
+    let lengths: Vec<f32> = data
+        .iter()
+        .map(|&enu| length(calibrated_measurement(measurement_to_enu(enu), calibration)))
+        .collect();
+    let max = lengths.iter().cloned().fold(f32::MIN, f32::max);
+    let min = lengths.iter().cloned().fold(f32::MAX, f32::min);
+    max / min
+}
+
+fn assert_center(calibration: &Calibration, expected: Vector) {
+    let c = calibration.center;
+    for (got, want) in [(c.x, expected.x), (c.y, expected.y), (c.z, expected.z)] {
+        assert!(
+            (got - want).abs() <= CALIBRATION_INCREMENT,
+            "center {:?} too far from {:?}",
+            c,
+            expected
+        );
+    }
+}
+
+#[test]
+fn centered_sphere() {
+    let center = Vector::new(0, 0, 0);
+    let data = sphere(25, center, EARTH_RADIUS);
+    let calibration = calibrate(&data);
+
+    assert_center(&calibration, center);
+    assert!((calibration.radius as f64 - EARTH_RADIUS).abs() < 0.01 * EARTH_RADIUS);
+    for s in [
+        calibration.scale.x,
+        calibration.scale.y,
+        calibration.scale.z,
+    ] {
+        assert!((1024..1060).contains(&s), "scale {:?}", calibration.scale);
+    }
+}
+
+#[test]
+fn offset_sphere() {
+    let center = Vector::new(-24_728, 32_424, 86_592);
+    let data = sphere(25, center, EARTH_RADIUS);
+    let calibration = calibrate(&data);
+
+    assert_center(&calibration, center);
+    assert!((calibration.radius as f64 - EARTH_RADIUS).abs() < 0.01 * EARTH_RADIUS);
+    assert!(spread(&data, &calibration) < 1.02);
+}
+
+#[test]
+fn offset_sphere_many_samples() {
+    let center = Vector::new(5_000, -12_000, 30_000);
+    let data = sphere(200, center, EARTH_RADIUS);
+    let calibration = calibrate(&data);
+
+    assert_center(&calibration, center);
+    assert!(measure_score(calibration.center, &data) < measure_score(Vector::default(), &data));
+}
+
+#[test]
+fn squashed_ellipsoid() {
+    let center = Vector::new(10_000, -20_000, 40_000);
+    let axes = [EARTH_RADIUS, EARTH_RADIUS, 0.8 * EARTH_RADIUS];
+    let data = ellipsoid(25, center, axes);
+    let calibration = calibrate(&data);
+
+    assert_center(&calibration, center);
+    // The radius is the longest axis, the short one gets stretched to match.
+    assert!((calibration.radius as f64 - axes[0]).abs() < 0.01 * axes[0]);
+    assert!(calibration.scale.z > calibration.scale.x);
+    assert!(calibration.scale.z > calibration.scale.y);
+
+    let uncorrected = Calibration {
+        center: calibration.center,
+        ..Calibration::default()
+    };
+    assert!(spread(&data, &calibration) < spread(&data, &uncorrected));
+}
+
+#[test]
+fn center_maps_to_zero() {
+    let calibration = Calibration {
+        center: Vector::new(-24_728, 32_424, 86_592),
+        scale: Vector::new(1289, 1309, 1348),
+        radius: 42_624,
+        ..Calibration::default()
+    };
+    let raw = measurement_to_enu(calibration.center);
+    assert_eq!(
+        calibrated_measurement(raw, &calibration),
+        Vector::new(0, 0, 0)
+    );
+}
+
+#[test]
+fn measurement_round_trip() {
+    let measurement = Measurement { x: 1, y: -2, z: 3 };
+    let vector = Vector::from(measurement);
+    assert_eq!(vector, Vector::new(1, -2, 3));
+    assert_eq!(Measurement::from(vector), measurement);
+}
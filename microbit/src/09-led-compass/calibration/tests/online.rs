@@ -0,0 +1,133 @@
+//! Streams simulated compass readings through the online calibration.
+
+use compass_calibration::online::{OnlineCalibration, Settings};
+use compass_calibration::quality::DIRECTIONS;
+use compass_calibration::{Algorithm, Calibration, Fit, Vector, CALIBRATION_INCREMENT};
+
+const EARTH_RADIUS: f32 = 42_000.0;
+
+/// Deterministic stream of random directions.
+struct Directions(u64);
+
+impl Directions {
+    fn next_f32(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A random point on the sphere, optionally only the upper half.
+    fn next(&mut self, center: Vector, upper_only: bool) -> Vector {
+        let mut z = 2.0 * self.next_f32() - 1.0;
+        if upper_only {
+            z = z.abs();
+        }
+        let theta = 2.0 * std::f32::consts::PI * self.next_f32();
+        let r = (1.0 - z * z).sqrt();
+        Vector::new(
+            center.x + (EARTH_RADIUS * r * theta.cos()) as i32,
+            center.y + (EARTH_RADIUS * r * theta.sin()) as i32,
+            center.z + (EARTH_RADIUS * z) as i32,
+        )
+    }
+}
+
+fn assert_center(fit: Fit, expected: Vector, tolerance: i32) {
+    let c = fit.center();
+    for (got, want) in [(c.x, expected.x), (c.y, expected.y), (c.z, expected.z)] {
+        assert!(
+            (got - want).abs() <= tolerance,
+            "center {:?} too far from {:?}",
+            c,
+            expected
+        );
+    }
+}
+
+#[test]
+fn converges_on_the_offset() {
+    let center = Vector::new(-24_728, 32_424, 86_592);
+    let mut online = OnlineCalibration::<48>::new(Settings::default());
+    let mut directions = Directions(1);
+
+    for _ in 0..500 {
+        online.add(directions.next(center, false));
+    }
+
+    assert!(online.coverage() >= Settings::default().min_coverage);
+    assert_center(online.fit().unwrap(), center, 4 * CALIBRATION_INCREMENT);
+}
+
+#[test]
+fn follows_a_moving_offset() {
+    let before = Vector::new(0, 0, 0);
+    let after = Vector::new(15_000, -10_000, 5_000);
+    let settings = Settings {
+        algorithm: Algorithm::Ellipsoid,
+        ..Settings::default()
+    };
+    let mut online = OnlineCalibration::<48>::new(settings);
+    let mut directions = Directions(2);
+
+    for _ in 0..500 {
+        online.add(directions.next(before, false));
+    }
+    assert_center(online.fit().unwrap(), before, 1_000);
+
+    for _ in 0..1_000 {
+        online.add(directions.next(after, false));
+    }
+    assert_center(online.fit().unwrap(), after, 1_000);
+}
+
+#[test]
+fn waits_for_coverage() {
+    let center = Vector::new(1_000, 2_000, 3_000);
+    let previous = Fit::Codal(Calibration {
+        center,
+        ..Calibration::default()
+    });
+    let mut online = OnlineCalibration::<48>::with_fit(Settings::default(), previous);
+    let mut directions = Directions(3);
+
+    // Only ever held face up, so half the sphere is missing.
+    for _ in 0..500 {
+        assert_eq!(online.add(directions.next(center, true)), None);
+    }
+    assert!(online.coverage() < Settings::default().min_coverage);
+    assert_eq!(online.fit(), Some(previous));
+}
+
+#[test]
+fn close_readings_replace_each_other() {
+    let mut online = OnlineCalibration::<8>::new(Settings::default());
+    for i in 0..100 {
+        online.add(Vector::new(40_000 + i, 0, 0));
+    }
+    assert_eq!(online.samples(), &[Vector::new(40_099, 0, 0)]);
+}
+
+#[test]
+fn stays_bounded() {
+    let mut online = OnlineCalibration::<16>::new(Settings::default());
+    let mut directions = Directions(4);
+    for _ in 0..1_000 {
+        online.add(directions.next(Vector::default(), false));
+        assert!(online.samples().len() <= 16);
+    }
+    assert!(online.coverage() <= DIRECTIONS);
+}
+
+#[test]
+fn full_coverage() {
+    let mut online = OnlineCalibration::<64>::new(Settings::default());
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                if (x, y, z) != (0, 0, 0) {
+                    online.add(Vector::new(40_000 * x, 40_000 * y, 40_000 * z));
+                }
+            }
+        }
+    }
+    assert_eq!(online.coverage(), DIRECTIONS);
+}
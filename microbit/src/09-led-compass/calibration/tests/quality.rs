@@ -0,0 +1,77 @@
+//! Checks that the quality metrics tell good calibration runs from bad ones.
+
+use compass_calibration::quality::{coverage, DIRECTIONS, EXPECTED_FIELD};
+use compass_calibration::{calibrate, fit_ellipsoid, Quality, Rejection, Thresholds, Vector};
+
+mod common;
+use common::{cap, sphere};
+
+#[test]
+fn good_run_passes() {
+    let data = sphere(25, Vector::new(-24_728, 32_424, 86_592), 42_000.0);
+    let quality = calibrate(&data).quality;
+
+    assert!(quality.spread < 0.02, "{}", quality);
+    assert!(quality.coverage > 80, "{}", quality);
+    assert!((quality.field_ratio - 42_000.0 / EXPECTED_FIELD).abs() < 0.01);
+    assert_eq!(quality.check(&Thresholds::default()), Ok(()));
+}
+
+#[test]
+fn ellipsoid_fit_is_measured_too() {
+    let data = sphere(25, Vector::new(1_000, 2_000, 3_000), 42_000.0);
+    let quality = fit_ellipsoid(&data).unwrap().quality;
+    assert_eq!(quality.check(&Thresholds::default()), Ok(()));
+}
+
+#[test]
+fn small_cap_is_rejected() {
+    // Barely moved the board at all
+    let data = cap(200, Vector::new(0, 0, 0), 42_000.0, 0.9);
+    let quality = calibrate(&data).quality;
+    assert_eq!(
+        quality.check(&Thresholds::default()),
+        Err(Rejection::Coverage)
+    );
+}
+
+#[test]
+fn noisy_run_is_rejected() {
+    let mut data = sphere(25, Vector::new(0, 0, 0), 42_000.0);
+    // Something magnetic went past while sampling
+    for point in data.iter_mut().step_by(3) {
+        point.x += 15_000;
+    }
+    let quality = calibrate(&data).quality;
+    assert_eq!(
+        quality.check(&Thresholds::default()),
+        Err(Rejection::Spread)
+    );
+}
+
+#[test]
+fn magnet_nearby_is_rejected() {
+    let data = sphere(25, Vector::new(0, 0, 0), 150_000.0);
+    let quality = calibrate(&data).quality;
+    assert_eq!(quality.check(&Thresholds::default()), Err(Rejection::Field));
+}
+
+#[test]
+fn coverage_of_a_full_sphere() {
+    let data = sphere(500, Vector::new(7, 8, 9), 42_000.0);
+    assert_eq!(coverage(Vector::new(7, 8, 9), &data), DIRECTIONS);
+    assert_eq!(coverage(Vector::new(7, 8, 9), &[]), 0);
+}
+
+#[test]
+fn display() {
+    let quality = Quality {
+        spread: 0.0412,
+        coverage: 61,
+        field_ratio: 0.853,
+    };
+    assert_eq!(
+        quality.to_string(),
+        "spread 4.1%, coverage 61%, field 85% of expected"
+    );
+}
@@ -0,0 +1,59 @@
+//! Round trips calibrations through the flash record format.
+
+use compass_calibration::record::{decode, encode, MAGIC, RECORD_LEN};
+use compass_calibration::{Calibration, EllipsoidCalibration, Fit, Quality, Vector};
+
+fn codal() -> Fit {
+    Fit::Codal(Calibration {
+        center: Vector::new(-24_728, 32_424, 86_592),
+        scale: Vector::new(1289, 1309, 1348),
+        radius: 42_624,
+        quality: Quality {
+            spread: 0.04,
+            coverage: 61,
+            field_ratio: 0.85,
+        },
+    })
+}
+
+fn ellipsoid() -> Fit {
+    Fit::Ellipsoid(EllipsoidCalibration {
+        offset: Vector::new(10_000, -20_000, 40_000),
+        soft_iron: [[1100, -35, 12], [-35, 980, -7], [12, -7, 1210]],
+        radius: 41_000,
+        quality: Quality {
+            spread: 0.01,
+            coverage: 88,
+            field_ratio: 0.82,
+        },
+    })
+}
+
+#[test]
+fn round_trip() {
+    for fit in [codal(), ellipsoid()] {
+        assert_eq!(decode(&encode(&fit)), Some(fit));
+    }
+}
+
+#[test]
+fn starts_with_magic() {
+    let record = encode(&codal());
+    assert_eq!(record[..4], MAGIC.to_le_bytes());
+}
+
+#[test]
+fn erased_flash_is_rejected() {
+    assert_eq!(decode(&[0xff; RECORD_LEN]), None);
+    assert_eq!(decode(&[0; RECORD_LEN]), None);
+}
+
+#[test]
+fn corruption_is_rejected() {
+    let record = encode(&ellipsoid());
+    for i in 0..RECORD_LEN {
+        let mut corrupted = record;
+        corrupted[i] ^= 0x10;
+        assert_eq!(decode(&corrupted), None, "flipped a bit in byte {}", i);
+    }
+}
@@ -0,0 +1,132 @@
+//! Magnetic declination, the angle between magnetic and true north.
+//!
+//! A compass points to magnetic north, which can be 20° or more away from
+//! the geographic north pole depending on where you are. Knowing the
+//! declination turns a magnetic heading into a true one.
+//!
+//! [`declination`] estimates it from a spherical harmonic model of the
+//! Earth's field: the IGRF-13 coefficients for 2020 cut off at degree 6,
+//! with their yearly change. That is a few hundred bytes instead of the 12
+//! degrees of the full WMM/IGRF and good to about 2° away from the poles,
+//! which is plenty for a 5x5 LED needle. For a better value look yours up,
+//! e.g. on <https://www.ngdc.noaa.gov/geomag/calculators/magcalc.shtml>.
+
+use core::f32::consts::PI;
+use libm::{atan2f, cosf, sinf, sqrtf};
+
+use crate::wrap;
+
+/// Highest degree of the model.
+const DEGREE: usize = 6;
+
+/// Year the coefficients are for.
+pub const MODEL_EPOCH: f32 = 2020.0;
+
+/// Schmidt semi-normalized Gauss coefficients (nT) as
+/// `(n, m, g, h, g per year, h per year)`.
+#[rustfmt::skip]
+const COEFFICIENTS: [(usize, usize, f32, f32, f32, f32); 27] = [
+    (1, 0, -29404.8,     0.0,   5.7,   0.0),
+    (1, 1,  -1450.9,  4652.5,   7.4, -25.9),
+    (2, 0,  -2499.6,     0.0, -11.0,   0.0),
+    (2, 1,   2982.0, -2991.6,  -7.0, -30.2),
+    (2, 2,   1677.0,  -734.6,  -2.1, -22.4),
+    (3, 0,   1363.2,     0.0,   2.2,   0.0),
+    (3, 1,  -2381.2,   -82.1,  -5.9,   6.0),
+    (3, 2,   1236.2,   241.9,   3.1,  -1.1),
+    (3, 3,    525.7,  -543.4, -12.0,   0.5),
+    (4, 0,    903.0,     0.0,  -1.2,   0.0),
+    (4, 1,    809.5,   281.9,  -1.6,  -0.1),
+    (4, 2,     86.3,  -158.4,  -5.9,   6.5),
+    (4, 3,   -309.4,   199.7,   5.2,   3.6),
+    (4, 4,     48.0,  -349.7,  -5.1,  -5.0),
+    (5, 0,   -234.3,     0.0,  -0.3,   0.0),
+    (5, 1,    363.2,    47.7,   0.5,   0.0),
+    (5, 2,    187.8,   208.3,  -0.6,   2.5),
+    (5, 3,   -140.7,  -121.2,   0.2,  -0.6),
+    (5, 4,   -151.2,    32.3,   1.3,   3.0),
+    (5, 5,     13.5,    98.9,   0.9,   0.3),
+    (6, 0,     66.0,     0.0,  -0.5,   0.0),
+    (6, 1,     65.5,   -19.1,  -0.3,   0.0),
+    (6, 2,     72.9,    25.1,   0.4,  -1.6),
+    (6, 3,   -121.5,    52.8,   1.3,  -1.3),
+    (6, 4,    -36.2,   -64.5,  -1.4,   0.8),
+    (6, 5,     13.5,     8.9,   0.0,   0.0),
+    (6, 6,    -64.7,    68.1,   0.9,   1.0),
+];
+
+/// Declination in radians at `latitude` and `longitude` (degrees, north and
+/// east positive) in `year`, positive if magnetic north is east of true
+/// north.
+///
+/// The Earth is treated as a sphere and the position as being on its
+/// surface, both errors are tiny next to the cut off model. Close to the
+/// magnetic poles the horizontal field vanishes and the result is
+/// meaningless.
+pub fn declination(latitude: f32, longitude: f32, year: f32) -> f32 {
+    let colatitude = (90.0 - latitude) * PI / 180.0;
+    let longitude = longitude * PI / 180.0;
+    let (sin_t, cos_t) = (sinf(colatitude), cosf(colatitude));
+    let (p, dp) = legendre(sin_t, cos_t);
+
+    // Northward and eastward components of the field.
+    let mut north = 0.0;
+    let mut east = 0.0;
+    let dt = year - MODEL_EPOCH;
+    for &(n, m, g, h, g_dot, h_dot) in COEFFICIENTS.iter() {
+        let g = g + g_dot * dt;
+        let h = h + h_dot * dt;
+        let (sin_ml, cos_ml) = (sinf(m as f32 * longitude), cosf(m as f32 * longitude));
+        north += (g * cos_ml + h * sin_ml) * dp[n][m];
+        if sin_t > f32::EPSILON {
+            east += m as f32 * (g * sin_ml - h * cos_ml) * p[n][m] / sin_t;
+        }
+    }
+    atan2f(east, north)
+}
+
+/// Indexed by degree and order.
+type Table = [[f32; DEGREE + 1]; DEGREE + 1];
+
+/// Schmidt semi-normalized associated Legendre functions of cos θ and their
+/// derivatives by θ.
+fn legendre(sin_t: f32, cos_t: f32) -> (Table, Table) {
+    let mut p = [[0.0; DEGREE + 1]; DEGREE + 1];
+    let mut dp = [[0.0; DEGREE + 1]; DEGREE + 1];
+    p[0][0] = 1.0;
+
+    for n in 1..=DEGREE {
+        let nf = n as f32;
+        // The diagonal builds on the previous one.
+        let k = if n == 1 {
+            1.0
+        } else {
+            sqrtf((2.0 * nf - 1.0) / (2.0 * nf))
+        };
+        p[n][n] = k * sin_t * p[n - 1][n - 1];
+        dp[n][n] = k * (sin_t * dp[n - 1][n - 1] + cos_t * p[n - 1][n - 1]);
+
+        // Everything below it from the two degrees before.
+        for m in 0..n {
+            let mf = m as f32;
+            let a = 2.0 * nf - 1.0;
+            let b = sqrtf((nf - 1.0 + mf) * (nf - 1.0 - mf));
+            let c = sqrtf((nf + mf) * (nf - mf));
+            let (p2, dp2) = if n >= 2 {
+                (p[n - 2][m], dp[n - 2][m])
+            } else {
+                (0.0, 0.0)
+            };
+            p[n][m] = (a * cos_t * p[n - 1][m] - b * p2) / c;
+            dp[n][m] = (a * (cos_t * dp[n - 1][m] - sin_t * p[n - 1][m]) - b * dp2) / c;
+        }
+    }
+    (p, dp)
+}
+
+/// Turns a heading towards magnetic north into one towards true north.
+/// Both are in the frame of [`crate::tilt_compensated_heading`], the result
+/// is between -π and π.
+pub fn true_heading(magnetic: f32, declination: f32) -> f32 {
+    wrap(magnetic + declination)
+}
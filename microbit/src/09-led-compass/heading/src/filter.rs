@@ -0,0 +1,117 @@
+//! Keeps the needle from flickering.
+//!
+//! The raw heading jitters by a few degrees from one reading to the next.
+//! [`HeadingFilter`] smooths it, and [`Sectors`] only lets the displayed
+//! direction change once the heading is clearly past the border between
+//! two of them.
+
+use core::f32::consts::PI;
+use libm::{atan2f, cosf, fabsf, roundf, sinf};
+
+use crate::wrap;
+
+/// Exponential low-pass filter for angles.
+///
+/// Averaging angles directly breaks where they wrap around: 179° and -179°
+/// average to 0° instead of 180°. So this averages the unit vectors
+/// pointing at them instead and returns the angle of the result.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadingFilter {
+    smoothing: f32,
+    /// Smoothed unit vector, `None` before the first heading.
+    state: Option<(f32, f32)>,
+}
+
+impl HeadingFilter {
+    /// `smoothing` is the weight of every new heading, from 1 (no smoothing
+    /// at all) down towards 0 (never moves). Values outside that range are
+    /// clamped.
+    pub fn new(smoothing: f32) -> HeadingFilter {
+        HeadingFilter {
+            smoothing: clamp_smoothing(smoothing),
+            state: None,
+        }
+    }
+
+    pub fn smoothing(&self) -> f32 {
+        self.smoothing
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = clamp_smoothing(smoothing);
+    }
+
+    /// Adds a heading in radians and returns the smoothed one, between -π
+    /// and π.
+    pub fn update(&mut self, heading: f32) -> f32 {
+        let (x, y) = (cosf(heading), sinf(heading));
+        let (x, y) = match self.state {
+            Some((sx, sy)) => (
+                sx + self.smoothing * (x - sx),
+                sy + self.smoothing * (y - sy),
+            ),
+            None => (x, y),
+        };
+        self.state = Some((x, y));
+        atan2f(y, x)
+    }
+
+    /// Forgets the history, the next heading is taken as is.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+fn clamp_smoothing(smoothing: f32) -> f32 {
+    // Exactly 0 would never leave the first heading.
+    smoothing.clamp(0.01, 1.0)
+}
+
+/// Splits the circle into `count` equal sectors, the first one centered on
+/// 0 and counting counter clockwise, with hysteresis at the borders.
+#[derive(Debug, Clone, Copy)]
+pub struct Sectors {
+    count: u32,
+    hysteresis: f32,
+    current: Option<u32>,
+}
+
+impl Sectors {
+    /// `hysteresis` is how far (radians) the heading has to go past the
+    /// border of the current sector before it switches, at most half a
+    /// sector.
+    pub fn new(count: u32, hysteresis: f32) -> Sectors {
+        let width = 2.0 * PI / count as f32;
+        Sectors {
+            count,
+            hysteresis: hysteresis.clamp(0.0, width / 2.0),
+            current: None,
+        }
+    }
+
+    /// Width of one sector in radians.
+    pub fn width(&self) -> f32 {
+        2.0 * PI / self.count as f32
+    }
+
+    /// Angle in the middle of `sector`.
+    pub fn center(&self, sector: u32) -> f32 {
+        wrap(sector as f32 * self.width())
+    }
+
+    /// Returns the sector for `heading` (radians), sticking to the previous
+    /// one while the heading is within the hysteresis of it.
+    pub fn update(&mut self, heading: f32) -> u32 {
+        if let Some(current) = self.current {
+            let distance = fabsf(wrap(heading - self.center(current)));
+            if distance <= self.width() / 2.0 + self.hysteresis {
+                return current;
+            }
+        }
+
+        let nearest = roundf(heading / self.width()) as i32;
+        let sector = nearest.rem_euclid(self.count as i32) as u32;
+        self.current = Some(sector);
+        sector
+    }
+}
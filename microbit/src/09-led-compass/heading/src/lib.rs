@@ -0,0 +1,107 @@
+//! Turns calibrated magnetometer readings into a compass heading.
+//!
+//! All vectors are in the board's cartesian frame as returned by
+//! `compass_calibration::calibrated_measurement`: x to the right, y towards
+//! the top edge (the one with the USB connector) and z out of the board.
+//! Headings are in radians, counter clockwise from the x axis, so a board
+//! that lies flat and points its y axis north reads π/2.
+//!
+//! [`declination`] turns the heading towards magnetic north into one towards
+//! true north, [`filter`] steadies it and [`needle`] draws the result on the
+//! LED matrix.
+
+#![no_std]
+
+pub mod declination;
+pub mod filter;
+pub mod needle;
+
+use compass_calibration::Vector;
+use core::f32::consts::PI;
+use libm::{atan2f, fabsf, fmodf, sqrtf};
+
+/// Below this the accelerometer is considered useless (e.g. free fall) and
+/// the heading falls back to [`flat_heading`].
+const MIN_GRAVITY: f32 = 100.0;
+
+/// Cosine of the angle between the y axis and gravity beyond which the y axis
+/// is too close to vertical to tell where the board points, about 72°.
+const MAX_AXIS_TILT: f32 = 0.95;
+
+/// Heading of a board lying flat, only correct if it actually does.
+pub fn flat_heading(mag: Vector) -> f32 {
+    atan2f(mag.y as f32, mag.x as f32)
+}
+
+/// Heading that stays correct while the board is tilted.
+///
+/// `accel` is the accelerometer reading in the same frame as `mag`, it gives
+/// the direction of gravity and with that the pitch and roll of the board.
+/// The board's x and y axes are rotated into the horizontal plane by
+/// projecting them along gravity, and the heading is measured in that
+/// plane. Only the direction of `accel` matters, so it doesn't care about
+/// units or which way the sensor's z axis points.
+///
+/// If the board is pitched so far that the y axis points almost straight up
+/// or down there is no sensible heading and this falls back to
+/// [`flat_heading`] as well.
+pub fn tilt_compensated_heading(mag: Vector, accel: Vector) -> f32 {
+    let g = to_f32(accel);
+    let g_len = length(g);
+    if g_len < MIN_GRAVITY {
+        return flat_heading(mag);
+    }
+    let g = scale(g, 1.0 / g_len);
+
+    let x = [1.0, 0.0, 0.0];
+    let y = [0.0, 1.0, 0.0];
+    if fabsf(dot(y, g)) > MAX_AXIS_TILT {
+        return flat_heading(mag);
+    }
+
+    // Gram-Schmidt: the y axis without its vertical part gives forward, the
+    // x axis without its vertical and forward parts gives right.
+    let forward = normalize(reject(y, g));
+    let right = normalize(reject(reject(x, g), forward));
+
+    let m = to_f32(mag);
+    atan2f(dot(m, forward), dot(m, right))
+}
+
+fn to_f32(v: Vector) -> [f32; 3] {
+    [v.x as f32, v.y as f32, v.z as f32]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    sqrtf(dot(v, v))
+}
+
+fn scale(v: [f32; 3], s: f32) -> [f32; 3] {
+    [v[0] * s, v[1] * s, v[2] * s]
+}
+
+/// `v` without its component along the unit vector `n`.
+fn reject(v: [f32; 3], n: [f32; 3]) -> [f32; 3] {
+    let d = dot(v, n);
+    [v[0] - d * n[0], v[1] - d * n[1], v[2] - d * n[2]]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    scale(v, 1.0 / length(v))
+}
+
+/// `angle` moved into -π to π.
+fn wrap(angle: f32) -> f32 {
+    let angle = fmodf(angle, 2.0 * PI);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle <= -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
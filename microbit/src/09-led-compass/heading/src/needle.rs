@@ -0,0 +1,46 @@
+//! Draws a compass needle at any angle on the 5x5 LED matrix.
+//!
+//! Most angles fall between the LEDs, so each LED gets a brightness that
+//! depends on how close its center is to the needle. The eye blends
+//! neighbouring LEDs, which makes the needle appear to sit in between them.
+//! The tip runs from the middle LED to the edge of the matrix, the tail is
+//! shorter and dimmer so you can tell both ends apart.
+
+use libm::{cosf, sinf, sqrtf};
+
+/// Brightness of a fully lit LED, the highest `GreyscaleImage` knows.
+pub const MAX_BRIGHTNESS: u8 = 9;
+
+/// Distance from the middle LED to the end of the tip, in LEDs.
+const TIP_LENGTH: f32 = 2.5;
+const TAIL_LENGTH: f32 = 1.5;
+/// Brightness of the tail relative to the tip.
+const TAIL_BRIGHTNESS: f32 = 0.35;
+
+/// Brightnesses from 0 to [`MAX_BRIGHTNESS`] for a needle pointing at `angle`
+/// radians, counter clockwise from pointing right. The result is indexed by
+/// row (top first) and then column, like the images the display takes.
+pub fn render(angle: f32) -> [[u8; 5]; 5] {
+    // Rows count downwards, so up is negative.
+    let (dx, dy) = (cosf(angle), -sinf(angle));
+
+    let mut leds = [[0; 5]; 5];
+    for (row, line) in leds.iter_mut().enumerate() {
+        for (col, led) in line.iter_mut().enumerate() {
+            let (x, y) = (col as f32 - 2.0, row as f32 - 2.0);
+            let tip = coverage(x, y, dx, dy, TIP_LENGTH);
+            let tail = TAIL_BRIGHTNESS * coverage(x, y, -dx, -dy, TAIL_LENGTH);
+            *led = (tip.max(tail) * MAX_BRIGHTNESS as f32 + 0.5) as u8;
+        }
+    }
+    leds
+}
+
+/// How much of the LED at (`x`, `y`) a line of `length` from the middle
+/// along the unit vector (`dx`, `dy`) covers: 1 if the line goes through
+/// its center, down to 0 at one LED away.
+fn coverage(x: f32, y: f32, dx: f32, dy: f32, length: f32) -> f32 {
+    let along = (x * dx + y * dy).clamp(0.0, length);
+    let (ex, ey) = (x - along * dx, y - along * dy);
+    (1.0 - sqrtf(ex * ex + ey * ey)).max(0.0)
+}
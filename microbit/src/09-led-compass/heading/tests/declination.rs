@@ -0,0 +1,57 @@
+//! Compares the cut off model against the full WMM2020 and checks the wrap
+//! around of the true heading.
+
+use compass_heading::declination::{declination, true_heading, MODEL_EPOCH};
+use std::f32::consts::PI;
+
+/// What the cut off model is expected to get right.
+const TOLERANCE: f32 = 2.5;
+
+#[test]
+fn matches_full_model() {
+    // Declination in degrees for 2020 from the NOAA calculator
+    let places = [
+        ("Boulder", 40.0, -105.25, 8.2),
+        ("New York", 40.7, -74.0, -12.9),
+        ("Berlin", 52.5, 13.4, 4.2),
+        ("Cape Town", -33.9, 18.4, -25.5),
+        ("Tokyo", 35.7, 139.7, -7.6),
+        ("Sydney", -33.87, 151.2, 12.8),
+    ];
+    for (name, latitude, longitude, expected) in places {
+        let got = declination(latitude, longitude, MODEL_EPOCH).to_degrees();
+        assert!(
+            (got - expected).abs() < TOLERANCE,
+            "{}: got {}°, expected {}°",
+            name,
+            got,
+            expected
+        );
+    }
+}
+
+#[test]
+fn longitude_wraps() {
+    let a = declination(40.0, -105.25, MODEL_EPOCH);
+    let b = declination(40.0, 360.0 - 105.25, MODEL_EPOCH);
+    assert!((a - b).abs() < 1e-3);
+}
+
+#[test]
+fn true_heading_adds_declination() {
+    let d = 10f32.to_radians();
+    assert!((true_heading(PI / 2.0, d) - (PI / 2.0 + d)).abs() < 1e-6);
+    assert!((true_heading(0.0, -d) + d).abs() < 1e-6);
+}
+
+#[test]
+fn true_heading_wraps() {
+    let d = 10f32.to_radians();
+    let east = true_heading(PI - 0.05, d);
+    assert!((east - (-PI - 0.05 + d)).abs() < 1e-5, "{}", east);
+    let west = true_heading(-PI + 0.05, -d);
+    assert!((west - (PI + 0.05 - d)).abs() < 1e-5, "{}", west);
+    for heading in [true_heading(PI, d), true_heading(-PI, -d)] {
+        assert!(heading > -PI && heading <= PI, "{}", heading);
+    }
+}
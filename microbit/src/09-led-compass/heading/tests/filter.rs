@@ -0,0 +1,130 @@
+//! Smoothing and sector hysteresis, mostly around the ±π wrap.
+
+use compass_heading::filter::{HeadingFilter, Sectors};
+use std::f32::consts::PI;
+
+fn deg(degrees: f32) -> f32 {
+    degrees.to_radians()
+}
+
+/// Difference between two angles, taking the wrap into account.
+fn angle_between(a: f32, b: f32) -> f32 {
+    ((a - b + 3.0 * PI).rem_euclid(2.0 * PI) - PI).abs()
+}
+
+#[test]
+fn first_heading_passes_through() {
+    let mut filter = HeadingFilter::new(0.2);
+    assert!(angle_between(filter.update(deg(123.0)), deg(123.0)) < 1e-5);
+}
+
+#[test]
+fn smooths_jitter() {
+    let mut filter = HeadingFilter::new(0.2);
+    filter.update(deg(90.0));
+    let out = filter.update(deg(100.0));
+    assert!(out > deg(90.0) && out < deg(95.0), "{}", out.to_degrees());
+}
+
+#[test]
+fn no_smoothing() {
+    let mut filter = HeadingFilter::new(1.0);
+    filter.update(deg(10.0));
+    assert!(angle_between(filter.update(deg(-70.0)), deg(-70.0)) < 1e-5);
+}
+
+#[test]
+fn average_across_wrap() {
+    let mut filter = HeadingFilter::new(0.5);
+    filter.update(deg(179.0));
+    let out = filter.update(deg(-179.0));
+    assert!(angle_between(out, PI) < deg(0.1), "{}", out.to_degrees());
+}
+
+#[test]
+fn jitter_across_wrap_stays_at_south() {
+    let mut filter = HeadingFilter::new(0.3);
+    for i in 0..100 {
+        let heading = if i % 2 == 0 { deg(175.0) } else { deg(-175.0) };
+        let out = filter.update(heading);
+        assert!(angle_between(out, PI) < deg(6.0), "{}", out.to_degrees());
+    }
+}
+
+#[test]
+fn follows_turn_across_wrap() {
+    let mut filter = HeadingFilter::new(0.3);
+    filter.update(deg(170.0));
+    let mut out = 0.0;
+    for _ in 0..50 {
+        out = filter.update(deg(-160.0));
+    }
+    assert!(
+        angle_between(out, deg(-160.0)) < deg(0.5),
+        "{}",
+        out.to_degrees()
+    );
+}
+
+#[test]
+fn smoothing_is_clamped() {
+    assert_eq!(HeadingFilter::new(2.0).smoothing(), 1.0);
+    assert!(HeadingFilter::new(-1.0).smoothing() > 0.0);
+}
+
+#[test]
+fn sectors_pick_nearest() {
+    let mut sectors = Sectors::new(8, 0.0);
+    assert_eq!(sectors.update(deg(0.0)), 0);
+    assert_eq!(sectors.update(deg(44.0)), 1);
+    assert_eq!(sectors.update(deg(-44.0)), 7);
+    assert_eq!(sectors.update(deg(180.0)), 4);
+    assert_eq!(sectors.update(deg(-180.0)), 4);
+}
+
+#[test]
+fn hysteresis_at_border() {
+    let mut sectors = Sectors::new(8, deg(5.0));
+    assert_eq!(sectors.update(deg(20.0)), 0);
+    // Border between 0 and 1 is at 22.5°
+    assert_eq!(sectors.update(deg(24.0)), 0);
+    assert_eq!(sectors.update(deg(21.0)), 0);
+    assert_eq!(sectors.update(deg(26.0)), 0);
+    assert_eq!(sectors.update(deg(28.0)), 1);
+    // and back again
+    assert_eq!(sectors.update(deg(21.0)), 1);
+    assert_eq!(sectors.update(deg(18.0)), 1);
+    assert_eq!(sectors.update(deg(17.0)), 0);
+}
+
+#[test]
+fn hysteresis_across_wrap() {
+    let mut sectors = Sectors::new(8, deg(5.0));
+    assert_eq!(sectors.update(deg(179.0)), 4);
+    for heading in [-179.0, 170.0, -160.0, 160.0, -155.0] {
+        assert_eq!(sectors.update(deg(heading)), 4, "{}", heading);
+    }
+    assert_eq!(sectors.update(deg(-150.0)), 5);
+    assert_eq!(sectors.update(deg(-159.0)), 5);
+    assert_eq!(sectors.update(deg(-163.0)), 4);
+}
+
+#[test]
+fn hysteresis_around_zero() {
+    // 11.25° wide, the border between 31 and 0 is at -5.625°
+    let mut sectors = Sectors::new(32, deg(2.0));
+    assert_eq!(sectors.update(deg(-7.0)), 31);
+    assert_eq!(sectors.update(deg(-5.0)), 31);
+    assert_eq!(sectors.update(deg(-4.0)), 31);
+    assert_eq!(sectors.update(deg(-3.0)), 0);
+    assert_eq!(sectors.update(deg(-7.0)), 0);
+    assert_eq!(sectors.update(deg(-8.0)), 31);
+}
+
+#[test]
+fn sector_centers() {
+    let sectors = Sectors::new(32, 0.0);
+    assert_eq!(sectors.center(0), 0.0);
+    assert!(angle_between(sectors.center(8), PI / 2.0) < 1e-5);
+    assert!(angle_between(sectors.center(31), -PI / 16.0) < 1e-5);
+}
@@ -0,0 +1,80 @@
+//! Checks that the needle points where it should and that it tells enough
+//! headings apart.
+
+use compass_heading::needle::{render, MAX_BRIGHTNESS};
+use std::f32::consts::PI;
+
+/// Center of brightness relative to the middle LED, with up positive.
+fn centroid(leds: &[[u8; 5]; 5]) -> (f32, f32) {
+    let (mut x, mut y, mut total) = (0.0, 0.0, 0.0);
+    for (row, line) in leds.iter().enumerate() {
+        for (col, &led) in line.iter().enumerate() {
+            x += led as f32 * (col as f32 - 2.0);
+            y += led as f32 * (2.0 - row as f32);
+            total += led as f32;
+        }
+    }
+    (x / total, y / total)
+}
+
+#[test]
+fn straight_up() {
+    let leds = render(PI / 2.0);
+    for row in 0..3 {
+        assert_eq!(leds[row][2], MAX_BRIGHTNESS, "{:?}", leds);
+    }
+    // Only the dim tail below the middle, fading out
+    assert!(
+        leds[3][2] > 0 && leds[3][2] < MAX_BRIGHTNESS / 2,
+        "{:?}",
+        leds
+    );
+    assert!(leds[4][2] < leds[3][2], "{:?}", leds);
+    for line in leds.iter() {
+        assert_eq!(line[0], 0);
+        assert_eq!(line[4], 0);
+    }
+}
+
+#[test]
+fn diagonal() {
+    let leds = render(-PI / 4.0);
+    assert_eq!(leds[2][2], MAX_BRIGHTNESS);
+    assert_eq!(leds[3][3], MAX_BRIGHTNESS);
+    assert!(leds[4][4] > MAX_BRIGHTNESS / 2, "{:?}", leds);
+    assert_eq!(leds[0][4], 0);
+    assert_eq!(leds[4][0], 0);
+}
+
+#[test]
+fn points_towards_angle() {
+    for i in 0..64 {
+        let angle = i as f32 * 2.0 * PI / 64.0;
+        let (x, y) = centroid(&render(angle));
+        let error = (y.atan2(x) - angle + 3.0 * PI).rem_euclid(2.0 * PI) - PI;
+        assert!(
+            error.abs() < 0.2,
+            "angle {} points to {}",
+            angle,
+            y.atan2(x)
+        );
+    }
+}
+
+#[test]
+fn at_least_32_headings() {
+    let images: Vec<_> = (0..32)
+        .map(|i| render(i as f32 * 2.0 * PI / 32.0))
+        .collect();
+    for (i, a) in images.iter().enumerate() {
+        for (j, b) in images.iter().enumerate().skip(i + 1) {
+            assert_ne!(a, b, "headings {} and {} look the same", i, j);
+        }
+    }
+}
+
+#[test]
+fn full_turn_is_the_same() {
+    assert_eq!(render(0.3), render(0.3 + 2.0 * PI));
+    assert_eq!(render(-0.3), render(2.0 * PI - 0.3));
+}
@@ -0,0 +1,131 @@
+//! Simulates a board in different orientations and checks that the tilt
+//! compensated heading matches the one the board would read lying flat.
+
+use compass_calibration::Vector;
+use compass_heading::{flat_heading, tilt_compensated_heading};
+use std::f32::consts::PI;
+
+/// World frame: x east, y north, z up. About what you get in central Europe,
+/// with the field dipping steeply into the ground.
+const FIELD: [f32; 3] = [0.0, 20_000.0, -45_000.0];
+const GRAVITY: [f32; 3] = [0.0, 0.0, 1_000.0];
+
+type Matrix = [[f32; 3]; 3];
+
+fn rot_x(a: f32) -> Matrix {
+    let (s, c) = a.sin_cos();
+    [[1.0, 0.0, 0.0], [0.0, c, -s], [0.0, s, c]]
+}
+
+fn rot_y(a: f32) -> Matrix {
+    let (s, c) = a.sin_cos();
+    [[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]]
+}
+
+fn rot_z(a: f32) -> Matrix {
+    let (s, c) = a.sin_cos();
+    [[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]]
+}
+
+fn mul(a: Matrix, b: Matrix) -> Matrix {
+    let mut out = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i][j] = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+/// Expresses a world vector in the frame of a board rotated by `r`.
+fn to_board(r: Matrix, v: [f32; 3]) -> Vector {
+    let mut out = [0.0; 3];
+    for i in 0..3 {
+        out[i] = (0..3).map(|k| r[k][i] * v[k]).sum();
+    }
+    Vector::new(out[0] as i32, out[1] as i32, out[2] as i32)
+}
+
+/// Board turned by `yaw`, then pitched around its x axis and rolled around
+/// its y axis.
+fn orientation(yaw: f32, pitch: f32, roll: f32) -> Matrix {
+    mul(rot_z(yaw), mul(rot_x(pitch), rot_y(roll)))
+}
+
+fn angle_diff(a: f32, b: f32) -> f32 {
+    let d = (a - b).rem_euclid(2.0 * PI);
+    d.min(2.0 * PI - d)
+}
+
+#[test]
+fn flat_board_matches_flat_heading() {
+    for yaw in (0..360).step_by(15) {
+        let r = rot_z((yaw as f32).to_radians());
+        let mag = to_board(r, FIELD);
+        let accel = to_board(r, GRAVITY);
+        let diff = angle_diff(tilt_compensated_heading(mag, accel), flat_heading(mag));
+        assert!(diff < 0.001, "yaw {}: off by {}", yaw, diff);
+    }
+}
+
+#[test]
+fn tilted_board_keeps_heading() {
+    for yaw in (0..360).step_by(30) {
+        let expected = flat_heading(to_board(rot_z((yaw as f32).to_radians()), FIELD));
+        for pitch in (-60..=60).step_by(15) {
+            for roll in (-60..=60).step_by(15) {
+                let r = orientation(
+                    (yaw as f32).to_radians(),
+                    (pitch as f32).to_radians(),
+                    (roll as f32).to_radians(),
+                );
+                let heading = tilt_compensated_heading(to_board(r, FIELD), to_board(r, GRAVITY));
+                let diff = angle_diff(heading, expected).to_degrees();
+                assert!(
+                    diff < 1.0,
+                    "yaw {} pitch {} roll {}: off by {}°",
+                    yaw,
+                    pitch,
+                    roll,
+                    diff
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn uncompensated_heading_is_off_when_tilted() {
+    let expected = flat_heading(to_board(rot_z(0.0), FIELD));
+    let r = orientation(0.0, 0.0, 40f32.to_radians());
+    let diff = angle_diff(flat_heading(to_board(r, FIELD)), expected).to_degrees();
+    assert!(diff > 30.0, "only off by {}°", diff);
+}
+
+#[test]
+fn accelerometer_sign_does_not_matter() {
+    let r = orientation(1.0, 0.5, -0.3);
+    let mag = to_board(r, FIELD);
+    let up = to_board(r, GRAVITY);
+    let down = Vector::new(-up.x, -up.y, -up.z);
+    let diff = angle_diff(
+        tilt_compensated_heading(mag, up),
+        tilt_compensated_heading(mag, down),
+    );
+    assert!(diff < 0.001);
+}
+
+#[test]
+fn free_fall_falls_back_to_flat_heading() {
+    let mag = Vector::new(1_000, 2_000, -3_000);
+    let heading = tilt_compensated_heading(mag, Vector::new(0, 0, 0));
+    assert_eq!(heading, flat_heading(mag));
+}
+
+#[test]
+fn board_on_its_edge_falls_back_to_flat_heading() {
+    let r = orientation(0.7, PI / 2.0, 0.0);
+    let mag = to_board(r, FIELD);
+    let heading = tilt_compensated_heading(mag, to_board(r, GRAVITY));
+    assert_eq!(heading, flat_heading(mag));
+}
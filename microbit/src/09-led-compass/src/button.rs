@@ -0,0 +1,44 @@
+//! Tells a deliberate long press apart from brushing against a button.
+
+use embedded_hal::digital::v2::InputPin;
+
+pub struct LongPress<P> {
+    pin: P,
+    /// Consecutive polls the button has been down for.
+    held: u32,
+    needed: u32,
+}
+
+impl<P: InputPin> LongPress<P> {
+    /// `needed` is the number of consecutive [`poll`](LongPress::poll)s the
+    /// button has to be down for. A button that is already down at this
+    /// point has to be released first, so holding it at boot doesn't count
+    /// twice.
+    pub fn new(pin: P, needed: u32) -> Self {
+        let mut button = LongPress {
+            pin,
+            held: 0,
+            needed,
+        };
+        if button.is_pressed() {
+            button.held = needed;
+        }
+        button
+    }
+
+    /// The buttons on the micro:bit pull their pin low.
+    pub fn is_pressed(&self) -> bool {
+        self.pin.is_low().unwrap_or(false)
+    }
+
+    /// Returns true once per press, when it has been held long enough.
+    pub fn poll(&mut self) -> bool {
+        if self.is_pressed() {
+            self.held = self.held.saturating_add(1);
+            self.held == self.needed
+        } else {
+            self.held = 0;
+            false
+        }
+    }
+}
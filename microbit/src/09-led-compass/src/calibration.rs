@@ -1,45 +1,59 @@
+//! Collects the samples for the calibration by having the user tilt the board
+//! until the whole LED matrix is lit. The math lives in the
+//! `compass-calibration` crate next to this chapter.
+//!
+//! The matrix shows the progress: every cell you have visited stays lit and
+//! the one you are tilting towards glows dimly. Runs whose [`Quality`]
+//! doesn't pass the default [`Thresholds`] are thrown away: the board blinks
+//! a cross and the user has to play again. A good run fades in a tick.
+//!
 //! Translated from <https://github.com/lancaster-university/codal-microbit-v2/blob/006abf5566774fbcf674c0c7df27e8a9d20013de/source/MicroBitCompassCalibrator.cpp>
 
+use common::data_ready::{next_accel, next_mag};
 use core::fmt::Debug;
 use embedded_hal::blocking::delay::DelayUs;
 use embedded_hal::blocking::i2c::{Write, WriteRead};
-use libm::{fabsf, sqrtf};
 use lsm303agr::interface::I2cInterface;
 use lsm303agr::mode::MagContinuous;
 use lsm303agr::Lsm303agr;
-use lsm303agr::Measurement;
-use microbit::display::blocking::Display;
+use rtt_target::rprintln;
+
+use crate::display;
+use crate::EVENTS;
+
+pub use compass_calibration::{
+    calibrate, calibrate_with, calibrated_measurement, measurement_to_enu, Algorithm, Calibration,
+    Fit, Quality, Thresholds, Vector,
+};
 
 const PERIMETER_POINTS: usize = 25;
 const PIXEL1_THRESHOLD: i32 = 200;
 const PIXEL2_THRESHOLD: i32 = 600;
-const CALIBRATION_INCREMENT: i32 = 200;
-
-#[derive(Debug)]
-pub struct Calibration {
-    pub center: Measurement,
-    pub scale: Measurement,
-    pub radius: u32,
-}
-
-impl Default for Calibration {
-    fn default() -> Calibration {
-        Calibration {
-            center: Measurement { x: 0, y: 0, z: 0 },
-            scale: Measurement {
-                x: 1024,
-                y: 1024,
-                z: 1024,
-            },
-            radius: 0,
-        }
-    }
-}
 
+/// Brightness of the cell under the cursor that hasn't been visited yet.
+const CURSOR_BRIGHTNESS: u8 = 3;
+
+const ACCEPTED: [[u8; 5]; 5] = [
+    [0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 9],
+    [0, 0, 0, 9, 0],
+    [9, 0, 9, 0, 0],
+    [0, 9, 0, 0, 0],
+];
+
+const REJECTED: [[u8; 5]; 5] = [
+    [9, 0, 0, 0, 9],
+    [0, 9, 0, 9, 0],
+    [0, 0, 9, 0, 0],
+    [0, 9, 0, 9, 0],
+    [9, 0, 0, 0, 9],
+];
+
+/// Plays the game until a run passes the quality check. The matrix has to
+/// be set up with [`display::init`] first.
 #[allow(dead_code)]
 pub fn calc_calibration<I, T, E>(
     sensor: &mut Lsm303agr<I2cInterface<I>, MagContinuous>,
-    display: &mut Display,
     timer: &mut T,
 ) -> Calibration
 where
@@ -47,17 +61,75 @@ where
     I: Write<Error = E> + WriteRead<Error = E>,
     E: Debug,
 {
-    let data = get_data(sensor, display, timer);
-    return calibrate(&data);
+    loop {
+        let data = get_data(sensor);
+        let calibration = calibrate(&data);
+        if accept(calibration.quality, timer) {
+            return calibration;
+        }
+    }
 }
 
-fn get_data<I, T, E>(
+/// Like [`calc_calibration`] but lets you pick the algorithm, e.g.
+/// `Algorithm::Ellipsoid` to also correct rotated soft iron distortion.
+#[allow(dead_code)]
+pub fn calc_calibration_with<I, T, E>(
+    algorithm: Algorithm,
     sensor: &mut Lsm303agr<I2cInterface<I>, MagContinuous>,
-    display: &mut Display,
     timer: &mut T,
-) -> [Measurement; 25]
+) -> Fit
+where
+    T: DelayUs<u32>,
+    I: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    loop {
+        let data = get_data(sensor);
+        let fit = calibrate_with(algorithm, &data);
+        if accept(fit.quality(), timer) {
+            return fit;
+        }
+    }
+}
+
+/// Reports `quality` over RTT and plays the matching animation.
+fn accept<T>(quality: Quality, timer: &mut T) -> bool
 where
     T: DelayUs<u32>,
+{
+    rprintln!("Calibration quality: {}", quality);
+    let accepted = match quality.check(&Thresholds::default()) {
+        Ok(()) => {
+            fade_in(ACCEPTED, timer);
+            timer.delay_us(1_000_000);
+            true
+        }
+        Err(rejection) => {
+            rprintln!("Calibration rejected ({}), please try again", rejection);
+            for _ in 0..3 {
+                display::show(REJECTED);
+                timer.delay_us(300_000);
+                display::clear();
+                timer.delay_us(200_000);
+            }
+            false
+        }
+    };
+    display::clear();
+    accepted
+}
+
+fn fade_in<T: DelayUs<u32>>(leds: [[u8; 5]; 5], timer: &mut T) {
+    for level in 1..=9 {
+        display::show(leds.map(|row| row.map(|led| led * level / 9)));
+        timer.delay_us(50_000);
+    }
+}
+
+fn get_data<I, E>(
+    sensor: &mut Lsm303agr<I2cInterface<I>, MagContinuous>,
+) -> [Vector; PERIMETER_POINTS]
+where
     I: Write<Error = E> + WriteRead<Error = E>,
     E: Debug,
 {
@@ -69,12 +141,11 @@ where
         [0, 0, 0, 0, 0],
     ];
     let mut cursor = (2, 2);
-    let mut data = [Measurement { x: 0, y: 0, z: 0 }; PERIMETER_POINTS];
+    let mut data = [Vector::default(); PERIMETER_POINTS];
     let mut samples = 0;
 
     while samples < PERIMETER_POINTS {
-        while !sensor.accel_status().unwrap().xyz_new_data {}
-        let accel_data = sensor.accel_data().unwrap();
+        let accel_data = next_accel(sensor, &EVENTS).unwrap();
         let x = accel_data.x;
         let y = accel_data.y;
         if x < -PIXEL2_THRESHOLD {
@@ -104,157 +175,21 @@ where
         // Turn the y axis properly
         cursor.0 = 4 - cursor.0;
 
-        if leds[cursor.0][cursor.1] != 1 {
-            leds[cursor.0][cursor.1] = 1;
-            while !sensor.mag_status().unwrap().xyz_new_data {}
-            let mag_data = measurement_to_enu(sensor.mag_data().unwrap());
-            data[samples] = mag_data;
-            samples += 1;
-        }
-        display.show(timer, leds, 200);
-    }
-    return data;
-}
-
-fn difference_square(a: Measurement, b: Measurement) -> f32 {
-    let dx = (a.x - b.x) as f32;
-    let dy = (a.y - b.y) as f32;
-    let dz = (a.z - b.z) as f32;
-
-    (dx * dx) + (dy * dy) + (dz * dz)
-}
-
-fn measure_score(center: Measurement, data: &[Measurement]) -> f32 {
-    let mut min_d = difference_square(center, data[0]);
-    let mut max_d = min_d;
-
-    for point in data[1..].iter() {
-        let d = difference_square(center, *point);
-        if d < min_d {
-            min_d = d;
-        }
-
-        if d > max_d {
-            max_d = d;
+        // The cursor is drawn before its cell counts as visited, so a new
+        // cell glows dimly while its sample is taken.
+        let mut frame = leds;
+        if frame[cursor.0][cursor.1] == 0 {
+            frame[cursor.0][cursor.1] = CURSOR_BRIGHTNESS;
         }
-    }
-
-    max_d - min_d
-}
-
-fn calibrate(data: &[Measurement]) -> Calibration {
-    // Approximate a center for the data
-    let mut center = Measurement { x: 0, y: 0, z: 0 };
-    let mut best = center;
-
-    for point in data {
-        center.x += point.x;
-        center.y += point.y;
-        center.z += point.z;
-    }
-
-    center.x = center.x / data.len() as i32;
-    center.y = center.y / data.len() as i32;
-    center.z = center.z / data.len() as i32;
-
-    let mut current = center;
-    let mut score = measure_score(current, data);
-
-    // Calculate a fixpoint position
-    loop {
-        for x in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
-            for y in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
-                for z in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
-                    let mut attempt = current;
-                    attempt.x += x;
-                    attempt.y += y;
-                    attempt.z += z;
-
-                    let attempt_score = measure_score(attempt, data);
-                    if attempt_score < score {
-                        score = attempt_score;
-                        best = attempt;
-                    }
-                }
-            }
-        }
-
-        if best == current {
-            break;
-        }
-
-        current = best;
-    }
+        display::show(frame);
 
-    spherify(current, data)
-}
-
-fn spherify(center: Measurement, data: &[Measurement]) -> Calibration {
-    let mut radius = 0;
-    for point in data {
-        let d = sqrtf(difference_square(center, *point)) as u32;
-        if d > radius {
-            radius = d;
+        if leds[cursor.0][cursor.1] != 9 {
+            leds[cursor.0][cursor.1] = 9;
+            let mag_data = measurement_to_enu(next_mag(sensor, &EVENTS).unwrap().into());
+            data[samples] = mag_data;
+            samples += 1;
+            rprintln!("Calibration sample {}/{}", samples, PERIMETER_POINTS);
         }
     }
-
-    let mut scale: f32 = 0.0;
-    let mut weight_x = 0.0;
-    let mut weight_y = 0.0;
-    let mut weight_z = 0.0;
-
-    for point in data {
-        let d = sqrtf(difference_square(center, *point));
-        let s = (radius as f32 / d) - 1.0;
-        scale = scale.max(s);
-
-        let dx = point.x - center.x;
-        let dy = point.y - center.y;
-        let dz = point.z - center.z;
-
-        weight_x += s * fabsf(dx as f32 / d);
-        weight_y += s * fabsf(dy as f32 / d);
-        weight_z += s * fabsf(dz as f32 / d);
-    }
-
-    let wmag = sqrtf((weight_x * weight_x) + (weight_y * weight_y) + (weight_z * weight_z));
-    let scale_x = 1.0 + scale * (weight_x / wmag);
-    let scale_y = 1.0 + scale * (weight_y / wmag);
-    let scale_z = 1.0 + scale * (weight_z / wmag);
-
-    Calibration {
-        center,
-        radius,
-        scale: Measurement {
-            x: (1024.0 * scale_x) as i32,
-            y: (1024.0 * scale_y) as i32,
-            z: (1024.0 * scale_z) as i32,
-        },
-    }
-}
-
-pub fn calibrated_measurement(measurement: Measurement, calibration: &Calibration) -> Measurement {
-    let mut out = measurement_to_enu(measurement);
-    out = Measurement {
-        x: ((out.x - calibration.center.x) * calibration.scale.x) >> 10,
-        y: ((out.y - calibration.center.y) * calibration.scale.y) >> 10,
-        z: ((out.z - calibration.center.z) * calibration.scale.z) >> 10,
-    };
-    enu_to_cartesian(out)
-}
-
-fn measurement_to_enu(measurement: Measurement) -> Measurement {
-    Measurement {
-        x: -measurement.y,
-        y: -measurement.x,
-        z: measurement.z,
-    }
-}
-
-fn enu_to_cartesian(measurement: Measurement) -> Measurement {
-    Measurement {
-        x: -measurement.y,
-        y: measurement.x,
-        z: measurement.z,
-    }
+    data
 }
@@ -0,0 +1,140 @@
+//! A small command line on the serial port for changing settings while the
+//! compass keeps running. Connect with e.g. `minicom -D /dev/ttyACM0 -b 115200`.
+//!
+//! ```text
+//! > declination 3.5
+//! > location 52.5 13.4
+//! > north true
+//! > smoothing 0.5
+//! ```
+
+use core::f32::consts::PI;
+use core::fmt::{Debug, Write};
+use core::mem;
+use core::str;
+use embedded_hal::serial;
+use heapless::Vec;
+
+use compass_heading::filter::HeadingFilter;
+
+use crate::north::{North, Reference};
+
+const HELP: &str = "commands:\r\n\
+    \x20 declination [degrees]    show or set, east positive\r\n\
+    \x20 location <lat> <long>    look the declination up, degrees\r\n\
+    \x20 north [true|magnetic]    show or set what the needle points to\r\n\
+    \x20 smoothing [0.01-1]       show or set the weight of new headings\r\n";
+
+pub struct Console<S> {
+    serial: S,
+    line: Vec<u8, 32>,
+}
+
+impl<S, E> Console<S>
+where
+    S: serial::Read<u8, Error = E> + serial::Write<u8, Error = E> + Write,
+    E: Debug,
+{
+    pub fn new(mut serial: S) -> Console<S> {
+        write!(serial, "> ").unwrap();
+        nb::block!(serial.flush()).unwrap();
+        Console {
+            serial,
+            line: Vec::new(),
+        }
+    }
+
+    /// Handles whatever arrived since the last call, without waiting for
+    /// more.
+    pub fn poll(&mut self, north: &mut North, filter: &mut HeadingFilter) {
+        while let Ok(b) = self.serial.read() {
+            if b == b'\r' || b == b'\n' {
+                write!(self.serial, "\r\n").unwrap();
+                let line = mem::take(&mut self.line);
+                if let Ok(line) = str::from_utf8(&line) {
+                    let line = line.trim();
+                    if !line.is_empty() {
+                        self.run(line, north, filter);
+                    }
+                }
+                write!(self.serial, "> ").unwrap();
+            } else {
+                // Echo chars as they're typed.
+                write!(self.serial, "{}", b as char).unwrap();
+
+                if self.line.push(b).is_err() {
+                    write!(self.serial, "\r\nline too long\r\n> ").unwrap();
+                    self.line.clear();
+                }
+            }
+            nb::block!(self.serial.flush()).unwrap();
+        }
+    }
+
+    fn run(&mut self, line: &str, north: &mut North, filter: &mut HeadingFilter) {
+        let mut args = line.split_whitespace();
+        let command = args.next().unwrap_or("");
+        let args: Vec<&str, 3> = args.take(3).collect();
+
+        match (command, args.as_slice()) {
+            ("declination", []) => {}
+            ("declination", [degrees]) => match degrees.parse::<f32>() {
+                Ok(degrees) => north.declination = degrees * PI / 180.0,
+                Err(_) => {
+                    write!(self.serial, "not a number: {:?}\r\n", degrees).unwrap();
+                    return;
+                }
+            },
+            ("location", [latitude, longitude]) => {
+                match (latitude.parse::<f32>(), longitude.parse::<f32>()) {
+                    (Ok(latitude), Ok(longitude))
+                        if (-90.0..=90.0).contains(&latitude)
+                            && (-180.0..=360.0).contains(&longitude) =>
+                    {
+                        north.set_location(latitude, longitude)
+                    }
+                    _ => {
+                        write!(self.serial, "expected latitude and longitude in degrees\r\n")
+                            .unwrap();
+                        return;
+                    }
+                }
+            }
+            ("north", []) => {}
+            ("north", ["true"]) => north.reference = Reference::True,
+            ("north", ["magnetic"]) => north.reference = Reference::Magnetic,
+            ("smoothing", []) => {
+                write!(self.serial, "smoothing {}\r\n", filter.smoothing()).unwrap();
+                return;
+            }
+            ("smoothing", [value]) => {
+                match value.parse::<f32>() {
+                    Ok(value) => filter.set_smoothing(value),
+                    Err(_) => write!(self.serial, "not a number: {:?}\r\n", value).unwrap(),
+                }
+                write!(self.serial, "smoothing {}\r\n", filter.smoothing()).unwrap();
+                return;
+            }
+            ("help", []) => {
+                self.serial.write_str(HELP).unwrap();
+                return;
+            }
+            _ => {
+                write!(self.serial, "invalid command {:?}, try help\r\n", line).unwrap();
+                return;
+            }
+        }
+
+        let reference = match north.reference {
+            Reference::Magnetic => "magnetic",
+            Reference::True => "true",
+        };
+        write!(
+            self.serial,
+            "declination {:.1}°, pointing to {} north\r\n",
+            north.declination * 180.0 / PI,
+            reference
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+//! The LED matrix, refreshed from the TIMER1 interrupt so that showing an
+//! image returns right away instead of blocking while it is on screen.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
+use microbit::display::nonblocking::{Display, GreyscaleImage};
+use microbit::gpio::DisplayPins;
+use microbit::pac::{self, interrupt, TIMER1};
+
+static DISPLAY: Mutex<RefCell<Option<Display<TIMER1>>>> = Mutex::new(RefCell::new(None));
+
+pub fn init(timer: TIMER1, pins: DisplayPins) {
+    let display = Display::new(timer, pins);
+    free(|cs| *DISPLAY.borrow(cs).borrow_mut() = Some(display));
+    // SAFETY: the handler only touches `DISPLAY`, which is behind a mutex.
+    unsafe { NVIC::unmask(pac::Interrupt::TIMER1) };
+}
+
+/// Shows `leds` until the next call. Each value is a brightness from 0 (off)
+/// to 9.
+pub fn show(leds: [[u8; 5]; 5]) {
+    let image = GreyscaleImage::new(&leds);
+    free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.show(&image);
+        }
+    });
+}
+
+pub fn clear() {
+    free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.clear();
+        }
+    });
+}
+
+#[interrupt]
+fn TIMER1() {
+    free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.handle_display_event();
+        }
+    });
+}
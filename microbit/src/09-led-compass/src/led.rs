@@ -1,3 +1,6 @@
+use compass_heading::needle;
+use core::f32::consts::PI;
+
 #[derive(Debug)]
 pub enum Direction{
     North,
@@ -10,79 +13,25 @@ pub enum Direction{
     NorthWest,
 }
 
-const NORTH: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 1, 1, 1, 0],   
-    [1, 0, 1, 0, 1],
-    [0, 0, 1, 0, 0],
-    [0, 0, 1, 0, 0],   
-];
-
-const NORTH_EAST: [[u8; 5]; 5] = [
-    [1, 1, 1, 0, 0],
-    [1, 1, 0, 0, 0],   
-    [1, 0, 1, 0, 0],
-    [0, 0, 0, 1, 0],
-    [0, 0, 0, 0, 1],   
-];
-
-const EAST: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 1, 0, 0, 0],   
-    [1, 1, 1, 1, 1],
-    [0, 1, 0, 0, 0],
-    [0, 0, 1, 0, 0],   
-];
-
-const SOUTH_EAST: [[u8; 5]; 5] = [
-    [0, 0, 0, 0, 1],
-    [0, 0, 0, 1, 0],   
-    [1, 0, 1, 0, 0],
-    [1, 1, 0, 0, 0],
-    [1, 1, 1, 0, 0],   
-];
-
-const SOUTH: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 0, 1, 0, 0],   
-    [1, 0, 1, 0, 1],
-    [0, 1, 1, 1, 0],
-    [0, 0, 1, 0, 0],   
-];
-
-const SOUTH_WEST: [[u8; 5]; 5] = [
-    [1, 0, 0, 0, 0],
-    [0, 1, 0, 0, 0],   
-    [0, 0, 1, 0, 1],
-    [0, 0, 0, 1, 1],
-    [0, 0, 1, 1, 1],   
-];
-
-const WEST: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 0, 0, 1, 0],   
-    [1, 1, 1, 1, 1],
-    [0, 0, 0, 1, 0],
-    [0, 0, 1, 0, 0],   
-];
-
-const NORTH_WEST: [[u8; 5]; 5] = [
-    [0, 0, 1, 1, 1],
-    [0, 0, 0, 1, 1],   
-    [0, 0, 1, 0, 1],
-    [0, 1, 0, 0, 0],
-    [1, 0, 0, 0, 0],   
-];
+impl Direction {
+    /// Which way the arrow for this direction points on the display, in
+    /// radians counter clockwise from pointing right.
+    pub fn arrow_angle(&self) -> f32 {
+        match self {
+            Direction::North => PI / 2.,
+            Direction::NorthEast => 3. / 4. * PI,
+            Direction::East => PI,
+            Direction::SouthEast => -3. / 4. * PI,
+            Direction::South => -PI / 2.,
+            Direction::SouthWest => -PI / 4.,
+            Direction::West => 0.,
+            Direction::NorthWest => PI / 4.,
+        }
+    }
+}
 
+/// Brightnesses from 0 to 9, meant for the non-blocking display. The
+/// blocking one from chapter 5 lights up everything that isn't 0.
 pub fn direction_to_led(direction: Direction) -> [[u8; 5]; 5] {
-    match direction {
-        Direction::North => NORTH,
-        Direction::NorthEast => NORTH_EAST,
-        Direction::East => EAST,
-        Direction::SouthEast => SOUTH_EAST,
-        Direction::South => SOUTH,
-        Direction::SouthWest => SOUTH_WEST,
-        Direction::West => WEST,
-        Direction::NorthWest => NORTH_WEST,
-    }
+    needle::render(direction.arrow_angle())
 }
@@ -2,49 +2,104 @@
 #![no_main]
 #![no_std]
 
-use calibration::Calibration;
-use cortex_m::interrupt;
+use calibration::{Calibration, Fit, Vector};
 use cortex_m_rt::entry;
 use lsm303agr::Measurement;
-use microbit::display::blocking::{Display as BlockingDisplay};
 use microbit::display::nonblocking::{Display, BitImage, GreyscaleImage};
 use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rprintln;
 
 mod calibration;
 use crate::calibration::calc_calibration;
+use crate::calibration::calc_calibration_with;
 use crate::calibration::calibrated_measurement;
+use crate::calibration::Algorithm;
+use compass_calibration::online::{OnlineCalibration, Settings};
+use compass_calibration::{enu_to_cartesian, measurement_to_enu};
+use compass_heading::filter::{HeadingFilter, Sectors};
+use compass_heading::{needle, tilt_compensated_heading};
+
+mod storage;
+use crate::storage::Storage;
+
+mod button;
+use crate::button::LongPress;
+
+mod display;
+
+mod north;
+use crate::north::North;
+
+mod console;
+use crate::console::Console;
+use common::cpu::Load;
+use common::data_ready::{self, next_mag, DataReady, Events, Int1};
+use common::serial::{BufferedUartePort, Buffers, UarteHandler};
 
 use microbit::hal::Timer;
 
 use microbit::{hal::twim, pac::twim0::frequency::FREQUENCY_A};
+use microbit::pac::{self, interrupt, TIMER0, TWIM0, UARTE0};
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
 
+use lsm303agr::interface::I2cInterface;
+use lsm303agr::mode::MagContinuous;
 use lsm303agr::{AccelOutputDataRate, Lsm303agr, MagOutputDataRate};
 
+type Sensor = Lsm303agr<I2cInterface<twim::Twim<TWIM0>>, MagContinuous>;
+
 // You'll find this useful ;-)
 use core::f32::consts::PI;
 use libm::{atan2f, sqrtf};
 
-const CALIBRATION: Calibration = Calibration {
-    center: Measurement {
-        x: -24728,
-        y: 32424,
-        z: 86592,
-    },
-    scale: Measurement {
-        x: 1289,
-        y: 1309,
-        z: 1348,
-    },
-    radius: 42624,
-};
+/// Size of the serial receive and transmit rings.
+const RX_SIZE: usize = 64;
+const TX_SIZE: usize = 256;
+
+static SERIAL: Mutex<RefCell<Option<UarteHandler<UARTE0, RX_SIZE, TX_SIZE>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Where the accelerometer's data ready interrupt leaves its events.
+pub static EVENTS: Events<4> = Events::new();
+static DATA_READY: Mutex<RefCell<Option<DataReady<4>>>> = Mutex::new(RefCell::new(None));
+
+/// Readings kept around for refining the calibration in the background.
+const ONLINE_SAMPLES: usize = 48;
+
+/// How long button A has to be held to start the calibration, in
+/// magnetometer readings (10 Hz).
+const LONG_PRESS_READINGS: u32 = 20;
+
+/// How often to report how busy the CPU is, in magnetometer readings.
+const LOAD_READINGS: u32 = 100;
+
+/// Weight of every new heading in the smoothed one, 1 turns smoothing off.
+/// Can be changed over serial with `smoothing`.
+const SMOOTHING: f32 = 0.3;
+
+/// Distinct positions of the needle.
+const NEEDLE_POSITIONS: u32 = 32;
+
+/// How far the heading has to move past the border between two needle
+/// positions before the needle follows (radians, about 2°).
+const HYSTERESIS: f32 = 0.035;
 
 #[entry]
 fn main() -> ! {
-    rtt_init_print!();
-    let board = microbit::Board::take().unwrap();
+    let (board, nvmc) = common::init_with_nvmc();
 
-    let i2c = twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100);
+    let mut load = Load::new(board.TIMER2);
+    let int = board.pins.p0_25.into_pullup_input().degrade();
+    let data_ready = DataReady::new(board.GPIOTE, int, &EVENTS);
+    free(|cs| *DATA_READY.borrow(cs).borrow_mut() = Some(data_ready));
+    unsafe { NVIC::unmask(pac::Interrupt::GPIOTE) };
+
+    let mut i2c = twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100);
+    // The magnetometer gets read whenever the accelerometer has a sample,
+    // both run at 10 Hz.
+    data_ready::route(&mut i2c, Int1::DataReady).unwrap();
 
     let mut sensor = Lsm303agr::new_with_i2c(i2c);
     sensor.init().unwrap();
@@ -52,20 +107,76 @@ fn main() -> ! {
     sensor.set_accel_odr(AccelOutputDataRate::Hz10).unwrap();
     let mut sensor = sensor.into_mag_continuous().ok().unwrap();
 
-    // let mut display = Display::new(board.TIMER0, board.display_pins);
-    // let calibration = calc_calibration(&mut sensor, &mut display, &mut timer);
-    // rprintln!("Calibration done, entering busy loop");
-
     let mut timer = Timer::new(board.TIMER0);
-    let mut display = BlockingDisplay::new(board.display_pins);
-
-    let calibration = CALIBRATION;
-    rprintln!("Using default calibration: {:?}", calibration);
-
+    display::init(board.TIMER1, board.display_pins);
+    let mut button_a = LongPress::new(board.buttons.button_a, LONG_PRESS_READINGS);
+
+    let mut north = North::from_env();
+    let mut filter = HeadingFilter::new(SMOOTHING);
+    let mut sectors = Sectors::new(NEEDLE_POSITIONS, HYSTERESIS);
+    let buffers = cortex_m::singleton!(: Buffers<RX_SIZE, TX_SIZE> = Buffers::new()).unwrap();
+    let (serial, handler) = BufferedUartePort::new(
+        common::uarte(board.UARTE0, board.uart, None),
+        board.TIMER3,
+        buffers,
+    );
+    free(|cs| *SERIAL.borrow(cs).borrow_mut() = Some(handler));
+    unsafe { NVIC::unmask(pac::Interrupt::UARTE0_UART0) };
+    let mut console = Console::new(serial);
+
+    let mut storage = Storage::new(nvmc);
+
+    let mut calibration = match storage.load() {
+        Some(calibration) if !button_a.is_pressed() => {
+            rprintln!("Loaded calibration from flash: {:?}", calibration);
+            rprintln!("Calibration quality: {}", calibration.quality());
+            calibration
+        }
+        Some(_) => {
+            rprintln!("Button A held, calibrating again");
+            recalibrate(&mut sensor, &mut storage, &mut timer)
+        }
+        None => {
+            rprintln!("No calibration in flash");
+            recalibrate(&mut sensor, &mut storage, &mut timer)
+        }
+    };
+
+    // Only kept in RAM, rewriting the flash page every few seconds would
+    // wear it out.
+    let mut online =
+        OnlineCalibration::<ONLINE_SAMPLES>::with_fit(Settings::default(), calibration);
+
+    let mut readings = 0;
     loop {
-        while !sensor.mag_status().unwrap().xyz_new_data {}
-        let mut data = sensor.mag_data().unwrap();
-        data = calibrated_measurement(data, &calibration);
+        // Asleep until there's a reading, the console waits as well.
+        console.poll(&mut north, &mut filter);
+        let data = next_mag(&mut sensor, &EVENTS).unwrap();
+
+        readings += 1;
+        if readings % LOAD_READINGS == 0 {
+            rprintln!("CPU busy {}% of the time", load.busy());
+        }
+
+        if button_a.poll() {
+            rprintln!("Button A long press, calibrating again");
+            calibration = recalibrate(&mut sensor, &mut storage, &mut timer);
+            online = OnlineCalibration::with_fit(Settings::default(), calibration);
+            filter.reset();
+            continue;
+        }
+
+        if let Some(refined) = online.add(measurement_to_enu(data.into())) {
+            rprintln!("Refined calibration: {:?}", refined);
+            rprintln!("Calibration quality: {}", refined.quality());
+            calibration = refined;
+        }
+        let data = calibrated_measurement(data.into(), &calibration);
+
+        // The accelerometer and the magnetometer of the LSM303AGR share the
+        // same axes, so the same rotation brings both into the board's frame.
+        let accel = sensor.accel_data().unwrap();
+        let accel = enu_to_cartesian(measurement_to_enu(accel.into()));
 
         // rprintln!("x: {}, y: {}, z: {}", data.x, data.y, data.z);
 
@@ -75,153 +186,42 @@ fn main() -> ! {
         let magnitude = sqrtf(x * x + y * y + z * z);
         rprintln!("{} nT, {} mG", magnitude, magnitude/100.);
 
-        // let dir = match (data.x > 0, data.y > 0) {
-        //     (true, true) => Direction::NorthEast,
-        //     (false, true) => Direction::NorthWest,
-        //     (false, false) => Direction::SouthWest,
-        //     (true, false) => Direction::SouthEast,
-        // };
-
-        // // If I'm facing a given direction, which way is north, relative to my
-        // // current direction?
-        // let arrow = match dir {
-        //     Direction::NorthEast => UP_LEFT,
-        //     Direction::NorthWest => UP_RIGHT,
-        //     Direction::SouthWest => DOWN_RIGHT,
-        //     Direction::SouthEast => DOWN_LEFT,
-        // };
-        // // let arrow = arrow.map(|row| row.map(|x| if x != 0 { 9 } else { 0 }));
-        
-        // let theta = atan2f(data.y as f32, data.x as f32);
-        // let dir = match theta {
-        //     _ if theta > PI / 2. => Direction::NorthWest,
-        //     _ if theta < -PI / 2. => Direction::SouthWest,
-        //     _ if theta >= 0. => Direction::NorthEast,
-        //     _ => Direction::SouthEast,
-        // };
-
-        // // If I'm facing a given direction, which way is north, relative to my
-        // // current direction?
-        // let arrow = match dir {
-        //     Direction::NorthEast => UP_LEFT,
-        //     Direction::NorthWest => UP_RIGHT,
-        //     Direction::SouthWest => DOWN_RIGHT,
-        //     Direction::SouthEast => DOWN_LEFT,
-        // };
-
-        let theta = atan2f(data.y as f32, data.x as f32);
-        let dir = match theta {
-            _ if theta > 7./8. * PI => Direction::West,
-            _ if theta > 5./8. * PI => Direction::NorthWest,
-            _ if theta > 3./8. * PI => Direction::North,
-            _ if theta > 1./8. * PI => Direction::NorthEast,
-
-            _ if theta < -7./8. * PI => Direction::West,
-            _ if theta < -5./8. * PI => Direction::SouthWest,
-            _ if theta < -3./8. * PI => Direction::South,
-            _ if theta < -1./8. * PI => Direction::SouthEast,
-
-            _ => Direction::East,
-        };
+        let theta = north.heading(tilt_compensated_heading(data, accel));
+        let theta = filter.update(theta);
+        let sector = sectors.update(theta);
 
         // If I'm facing a given direction, which way is north, relative to my
         // current direction?
-        let arrow = match dir {
-            Direction::NorthEast => UP_LEFT,
-            Direction::NorthWest => UP_RIGHT,
-            Direction::SouthWest => DOWN_RIGHT,
-            Direction::SouthEast => DOWN_LEFT,
-
-            Direction::North => UP,
-            Direction::South => DOWN,
-            Direction::East => LEFT,
-            Direction::West => RIGHT,
-        };
-
-        display.show(&mut timer, arrow, 100 /* ms */);
-        // interrupt::free(|_| display.show(&BitImage::new(&arrow)));
+        display::show(needle::render(PI - sectors.center(sector)));
     }
 }
 
-// enum Direction {
-//     NorthEast,
-//     NorthWest,
-//     SouthEast,
-//     SouthWest,
-// }
-
-enum Direction {
-    North,
-    East,
-    South,
-    West,
-    NorthEast,
-    NorthWest,
-    SouthEast,
-    SouthWest,
+#[interrupt]
+fn UARTE0_UART0() {
+    free(|cs| {
+        if let Some(handler) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            handler.handle_interrupt();
+        }
+    });
+}
+
+#[interrupt]
+fn GPIOTE() {
+    free(|cs| {
+        if let Some(data_ready) = DATA_READY.borrow(cs).borrow_mut().as_mut() {
+            data_ready.handle_interrupt();
+        }
+    });
 }
 
-const UP_LEFT: [[u8; 5]; 5] = [
-    [1, 1, 1, 1, 0],
-    [1, 1, 0, 0, 0],
-    [1, 0, 1, 0, 0],
-    [1, 0, 0, 1, 0],
-    [0, 0, 0, 0, 1],
-];
-
-const UP_RIGHT: [[u8; 5]; 5] = [
-    [0, 1, 1, 1, 1],
-    [0, 0, 0, 1, 1],
-    [0, 0, 1, 0, 1],
-    [0, 1, 0, 0, 1],
-    [1, 0, 0, 0, 0],
-];
-
-const DOWN_LEFT: [[u8; 5]; 5] = [
-    [0, 0, 0, 0, 1],
-    [1, 0, 0, 1, 0],
-    [1, 0, 1, 0, 0],
-    [1, 1, 0, 0, 0],
-    [1, 1, 1, 1, 0],
-];
-
-const DOWN_RIGHT: [[u8; 5]; 5] = [
-    [1, 0, 0, 0, 0],
-    [0, 1, 0, 0, 1],
-    [0, 0, 1, 0, 1],
-    [0, 0, 0, 1, 1],
-    [0, 1, 1, 1, 1],
-];
-
-const UP: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 1, 1, 1, 0],
-    [1, 0, 1, 0, 1],
-    [0, 0, 1, 0, 0],
-    [0, 0, 1, 0, 0],
-];
-
-
-const DOWN: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 0, 1, 0, 0],
-    [1, 0, 1, 0, 1],
-    [0, 1, 1, 1, 0],
-    [0, 0, 1, 0, 0],
-];
-
-const LEFT: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 1, 0, 0, 0],
-    [1, 1, 1, 1, 1],
-    [0, 1, 0, 0, 0],
-    [0, 0, 1, 0, 0],
-];
-
-const RIGHT: [[u8; 5]; 5] = [
-    [0, 0, 1, 0, 0],
-    [0, 0, 0, 1, 0],
-    [1, 1, 1, 1, 1],
-    [0, 0, 0, 1, 0],
-    [0, 0, 1, 0, 0],
-];
+/// Plays the calibration game and stores the result.
+fn recalibrate(sensor: &mut Sensor, storage: &mut Storage, timer: &mut Timer<TIMER0>) -> Fit {
+    rprintln!("Tilt the board to light up all LEDs");
+    let calibration = calc_calibration_with(Algorithm::Codal, sensor, timer);
+    match storage.save(&calibration) {
+        Ok(()) => rprintln!("Saved calibration to flash: {:?}", calibration),
+        // Still good until the next reset.
+        Err(e) => rprintln!("Couldn't save the calibration to flash: {:?}", e),
+    }
+    calibration
+}
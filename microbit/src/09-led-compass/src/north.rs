@@ -0,0 +1,78 @@
+//! Which north the needle points to.
+//!
+//! The declination can be given at build time, either directly or as the
+//! place the compass is used at:
+//!
+//! ```text
+//! COMPASS_DECLINATION=3.5 cargo embed
+//! COMPASS_LATITUDE=52.5 COMPASS_LONGITUDE=13.4 cargo embed
+//! ```
+//!
+//! Degrees, north and east positive. `COMPASS_YEAR` sets the year the model
+//! is evaluated for. Either way the compass starts pointing to true north,
+//! otherwise to magnetic north until a declination is set over serial.
+
+use compass_heading::declination::{declination, true_heading};
+use core::f32::consts::PI;
+
+/// Year the declination model is evaluated for, unless `COMPASS_YEAR` says
+/// otherwise.
+const DEFAULT_YEAR: f32 = 2025.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    Magnetic,
+    True,
+}
+
+pub struct North {
+    pub reference: Reference,
+    /// Radians, positive if magnetic north is east of true north.
+    pub declination: f32,
+}
+
+impl North {
+    /// Reads the settings the firmware was built with.
+    pub fn from_env() -> North {
+        let year = parse(option_env!("COMPASS_YEAR")).unwrap_or(DEFAULT_YEAR);
+        let location = parse(option_env!("COMPASS_LATITUDE"))
+            .zip(parse(option_env!("COMPASS_LONGITUDE")));
+
+        let declination = match (parse(option_env!("COMPASS_DECLINATION")), location) {
+            (Some(degrees), _) => Some(degrees * PI / 180.0),
+            (None, Some((latitude, longitude))) => Some(declination(latitude, longitude, year)),
+            (None, None) => None,
+        };
+
+        match declination {
+            Some(declination) => North {
+                reference: Reference::True,
+                declination,
+            },
+            None => North {
+                reference: Reference::Magnetic,
+                declination: 0.0,
+            },
+        }
+    }
+
+    /// Looks up the declination at `latitude` and `longitude` (degrees) in
+    /// the embedded model.
+    pub fn set_location(&mut self, latitude: f32, longitude: f32) {
+        let year = parse(option_env!("COMPASS_YEAR")).unwrap_or(DEFAULT_YEAR);
+        self.declination = declination(latitude, longitude, year);
+    }
+
+    /// The heading towards the selected north, given the one towards
+    /// magnetic north.
+    pub fn heading(&self, magnetic: f32) -> f32 {
+        match self.reference {
+            Reference::Magnetic => magnetic,
+            Reference::True => true_heading(magnetic, self.declination),
+        }
+    }
+}
+
+fn parse(value: Option<&str>) -> Option<f32> {
+    value?.trim().parse().ok()
+}
@@ -0,0 +1,49 @@
+//! Keeps the calibration in the flash page that `calibration.x` reserves,
+//! so it survives a reset and only has to be done once per board.
+//!
+//! The record format (magic number, CRC) lives in the `compass-calibration`
+//! crate, this only moves the bytes in and out of flash through the NVMC.
+
+use compass_calibration::record::{self, RECORD_LEN};
+use compass_calibration::Fit;
+use core::ptr::{addr_of, addr_of_mut};
+use core::slice;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use microbit::hal::nvmc::{Nvmc, NvmcError};
+use microbit::pac::NVMC;
+
+extern "C" {
+    // Defined in `calibration.x`
+    static mut _calibration_start: u32;
+    static _calibration_end: u32;
+}
+
+pub struct Storage(Nvmc<NVMC>);
+
+impl Storage {
+    pub fn new(nvmc: NVMC) -> Storage {
+        // SAFETY: the linker keeps the program out of this page, and owning
+        // the only `NVMC` means there is only ever one `Storage` handing it out.
+        let page = unsafe {
+            let start = addr_of_mut!(_calibration_start);
+            let words = (addr_of!(_calibration_end) as usize - start as usize) / 4;
+            slice::from_raw_parts_mut(start, words)
+        };
+        Storage(Nvmc::new(nvmc, page))
+    }
+
+    /// Returns the stored calibration, or `None` if the page is erased or the
+    /// record is damaged.
+    pub fn load(&mut self) -> Option<Fit> {
+        let mut bytes = [0; RECORD_LEN];
+        self.0.read(0, &mut bytes).ok()?;
+        record::decode(&bytes)
+    }
+
+    /// Replaces the stored calibration.
+    pub fn save(&mut self, fit: &Fit) -> Result<(), NvmcError> {
+        let bytes = record::encode(fit);
+        self.0.erase(0, Nvmc::<NVMC>::ERASE_SIZE as u32)?;
+        self.0.write(0, &bytes)
+    }
+}
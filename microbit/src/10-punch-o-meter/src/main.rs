@@ -3,73 +3,98 @@
 #![no_main]
 #![no_std]
 
+use core::cell::RefCell;
 use core::cmp::max;
 
-use cortex_m::prelude::_embedded_hal_timer_CountDown;
+use common::cpu::Load;
+use common::data_ready::{self, DataReady, Events, Int1};
+use common::fifo::AccelFifo;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
 use cortex_m_rt::entry;
 use lsm303agr::{Lsm303agr, AccelOutputDataRate, AccelScale};
-use microbit::hal::{twim::{self, Frequency}, timer::Timer};
+use microbit::hal::twim::{self, Frequency};
+use microbit::pac::{self, interrupt};
 use rtt_target::{rtt_init_print, rprint, rprintln};
 use panic_rtt_target as _;
 
+/// Samples a second, and how many of them make up a punch.
+const ODR: u32 = 400;
+/// The FIFO takes 32 samples, 80 ms at 400 Hz. Fetching them once there
+/// are 16 leaves plenty of room.
+const WATERMARK: u8 = 16;
+
+static EVENTS: Events<4> = Events::new();
+static DATA_READY: Mutex<RefCell<Option<DataReady<4>>>> = Mutex::new(RefCell::new(None));
+
 #[entry]
 fn main() -> ! {
-    rtt_init_print!();
-    let board = microbit::Board::take().unwrap();
+    let board = common::init();
+
+    let mut load = Load::new(board.TIMER2);
 
-    let mut timer = Timer::one_shot(board.TIMER0);
+    let int = board.pins.p0_25.into_pullup_input().degrade();
+    let data_ready = DataReady::new(board.GPIOTE, int, &EVENTS);
+    free(|cs| *DATA_READY.borrow(cs).borrow_mut() = Some(data_ready));
+    unsafe { NVIC::unmask(pac::Interrupt::GPIOTE) };
 
     let i2c = twim::Twim::new(board.TWIM0, board.i2c_internal.into(), Frequency::K100);
 
     let mut sensor = Lsm303agr::new_with_i2c(i2c);
-    sensor.set_accel_odr(AccelOutputDataRate::Hz400).unwrap(); // todo
+    sensor.init().unwrap();
+    sensor.set_accel_odr(AccelOutputDataRate::Hz400).unwrap();
     sensor.set_accel_scale(AccelScale::G16).unwrap();
+    let (mode, scale) = (sensor.get_accel_mode(), sensor.get_accel_scale());
+    let mut i2c = sensor.destroy();
+    data_ready::route(&mut i2c, Int1::Watermark).unwrap();
+    let mut fifo = AccelFifo::new(i2c, mode, scale, WATERMARK).unwrap();
 
-    // rprint!("...");
-    // timer.start(1_000u32); // ns
-    // loop {
-    //     match timer.wait() {
-    //         Ok(()) => break,
-    //         Err(nb::Error::WouldBlock) => (),
-    //         Err(nb::Error::Other(never)) => void::unreachable(never),
-    //     }
-    // }
-    // rprint!("done.");
-    // loop {}
-
-
+    // Samples left in the current punch, 0 while waiting for one.
+    let mut remaining = 0;
+    let (mut max_x, mut max_y, mut max_z) = (0, 0, 0);
     loop {
-        while !sensor.accel_status().unwrap().xyz_new_data {}
-        let data = sensor.accel_data().unwrap();
-        // rprintln!("{:?}", data);
-
-        if data.x > THRESH {
-            let mut max_x = data.x;
-            let mut max_y = data.y;
-            let mut max_z = data.z;
+        // Asleep until the FIFO reaches the watermark. WTM stays set until
+        // the FIFO is read, without another edge, so it's the flag that
+        // counts, like in `next_accel`.
+        while !fifo.watermark_reached().unwrap() {
+            EVENTS.wait();
+        }
+        let batch = fifo.read().unwrap();
+        if batch.overrun {
+            rprintln!("(missed some samples)");
+        }
 
-            rprint!("... ");
-            timer.start(1_000_000u32); // (1M ns = 1 second)
-            loop {
-                match timer.wait() {
-                    Ok(()) => break,
-                    Err(nb::Error::WouldBlock) => (),
-                    Err(nb::Error::Other(never)) => void::unreachable(never),
+        for data in &batch.samples {
+            if remaining == 0 {
+                if data.x > THRESH {
+                    rprint!("... ");
+                    remaining = ODR;
+                    max_x = data.x;
+                    max_y = data.y;
+                    max_z = data.z;
                 }
+                continue;
+            }
 
-                while !sensor.accel_status().unwrap().xyz_new_data {}
-                let data = sensor.accel_data().unwrap();
-                // if data.x < THRESH {
-                //     break;
-                // }
-                
-                max_x = max(max_x, data.x);
-                max_y = max(max_y, data.y);
-                max_z = max(max_z, data.z);
+            max_x = max(max_x, data.x);
+            max_y = max(max_y, data.y);
+            max_z = max(max_z, data.z);
+            remaining -= 1;
+            if remaining == 0 {
+                rprintln!("you punched: {} !  ({} {})", max_x, max_y, max_z);
+                rprintln!("CPU busy {}% of the time", load.busy());
             }
-            rprintln!("you punched: {} !  ({} {})", max_x, max_y, max_z);
         }
     }
 }
 
 const THRESH: i32 = 2_000;
+
+#[interrupt]
+fn GPIOTE() {
+    free(|cs| {
+        if let Some(data_ready) = DATA_READY.borrow(cs).borrow_mut().as_mut() {
+            data_ready.handle_interrupt();
+        }
+    });
+}